@@ -0,0 +1,81 @@
+use crate::data::{AuxBusSettings, AuxEffect};
+use super::delay::StereoDelay;
+use super::reverb::Reverb;
+
+/// One aux send/return bus. Both units are allocated up front so switching the
+/// effect never allocates; only the selected one runs.
+pub struct AuxUnit {
+    settings: AuxBusSettings,
+    delay: StereoDelay,
+    reverb: Reverb,
+}
+
+impl AuxUnit {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            settings: AuxBusSettings::OFF,
+            delay: StereoDelay::new(sample_rate),
+            reverb: Reverb::new(sample_rate),
+        }
+    }
+
+    pub fn set(&mut self, settings: AuxBusSettings) {
+        // A silent bus stops running, so whatever was left in its buffers would play
+        // back as stale repeats once it's heard again; start those from silence instead.
+        if (self.is_silent() && !Self::silent(&settings)) || settings.effect != self.settings.effect {
+            self.delay.clear();
+            self.reverb.clear();
+        }
+        self.settings = settings;
+        self.delay.set(settings.delay);
+        self.reverb.set(settings.reverb);
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.delay.set_tempo(bpm);
+    }
+
+    fn silent(settings: &AuxBusSettings) -> bool {
+        settings.muted || settings.return_level <= 0.0
+    }
+
+    fn is_silent(&self) -> bool {
+        Self::silent(&self.settings)
+    }
+
+    /// Runs the summed sends through the bus effect and returns it at the return level.
+    /// A muted or silent bus doesn't run at all.
+    pub fn process(&mut self, input: f32) -> [f32; 2] {
+        if self.is_silent() {
+            return [0.0; 2];
+        }
+        let level = self.settings.return_level.min(1.0);
+        let [left, right] = match self.settings.effect {
+            AuxEffect::Delay => self.delay.process(input),
+            AuxEffect::Reverb => self.reverb.process(input),
+        };
+        [left * level, right * level]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unmuting_a_bus_does_not_play_stale_repeats() {
+        let mut settings = AuxBusSettings::OFF;
+        settings.muted = false;
+        settings.return_level = 1.0;
+        settings.delay.feedback = 0.5;
+
+        let mut bus = AuxUnit::new(100.0);
+        bus.set(settings);
+        bus.process(1.0);
+        bus.set(AuxBusSettings { muted: true, ..settings });
+        bus.set(settings);
+
+        // Three seconds covers the longest delay line
+        assert!((0..300).all(|_| bus.process(0.0) == [0.0; 2]));
+    }
+}
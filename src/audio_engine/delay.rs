@@ -0,0 +1,101 @@
+use crate::data::{DelaySettings, MIN_BPM};
+
+/// Longest delay line we allocate for: a half note at the slowest tempo Fxx can set.
+const MAX_DELAY_SECONDS: f32 = 2.0 * 60.0 / MIN_BPM as f32;
+
+/// Tempo-synced stereo delay with damped feedback and optional ping-pong.
+/// Both lines are allocated up front so changing time or tempo never allocates.
+pub struct StereoDelay {
+    settings: DelaySettings,
+    sample_rate: f32,
+    bpm: f32,
+    buffers: [Vec<f32>; 2],
+    write_pos: usize,
+    delay_samples: usize,
+    damp_state: [f32; 2],
+}
+
+impl StereoDelay {
+    pub fn new(sample_rate: f32) -> Self {
+        let len = (MAX_DELAY_SECONDS * sample_rate) as usize + 1;
+        let mut delay = Self {
+            settings: DelaySettings::default(),
+            sample_rate,
+            bpm: 120.0,
+            buffers: [vec![0.0; len], vec![0.0; len]],
+            write_pos: 0,
+            delay_samples: 1,
+            damp_state: [0.0; 2],
+        };
+        delay.update_time();
+        delay
+    }
+
+    pub fn set(&mut self, settings: DelaySettings) {
+        self.settings = settings;
+        self.update_time();
+    }
+
+    pub fn set_tempo(&mut self, bpm: f32) {
+        if bpm != self.bpm {
+            self.bpm = bpm;
+            self.update_time();
+        }
+    }
+
+    fn update_time(&mut self) {
+        let seconds = self.settings.division.beats() * 60.0 / self.bpm.max(1.0);
+        let max = self.buffers[0].len() - 1;
+        self.delay_samples = ((seconds * self.sample_rate) as usize).clamp(1, max);
+    }
+
+    /// Drops any repeats still in the buffers.
+    pub fn clear(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.fill(0.0);
+        }
+        self.damp_state = [0.0; 2];
+    }
+
+    /// Takes the summed send signal and returns the wet stereo return.
+    pub fn process(&mut self, input: f32) -> [f32; 2] {
+        let len = self.buffers[0].len();
+        let read_pos = (self.write_pos + len - self.delay_samples) % len;
+        let taps = [self.buffers[0][read_pos], self.buffers[1][read_pos]];
+
+        // One-pole lowpass in the feedback path; damping 1.0 darkens each repeat heavily
+        let coef = self.settings.damping.clamp(0.0, 0.95);
+        let mut fed = [0.0; 2];
+        for (i, tap) in taps.iter().enumerate() {
+            self.damp_state[i] = tap * (1.0 - coef) + self.damp_state[i] * coef;
+            fed[i] = self.damp_state[i] * self.settings.feedback.clamp(0.0, 0.95);
+        }
+
+        let (left_in, right_in) = if self.settings.ping_pong {
+            // Dry signal enters on the left only; each side feeds the other
+            (input + fed[1], fed[0])
+        } else {
+            (input + fed[0], input + fed[1])
+        };
+        self.buffers[0][self.write_pos] = left_in;
+        self.buffers[1][self.write_pos] = right_in;
+        self.write_pos = (self.write_pos + 1) % len;
+
+        taps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::DelayDivision;
+
+    #[test]
+    fn a_half_note_fits_at_the_slowest_tempo() {
+        let sample_rate = 1000.0;
+        let mut delay = StereoDelay::new(sample_rate);
+        delay.set(DelaySettings { division: DelayDivision::Half, ..DelaySettings::default() });
+        delay.set_tempo(MIN_BPM as f32);
+        assert_eq!(delay.delay_samples, 3750);
+    }
+}
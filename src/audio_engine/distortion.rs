@@ -0,0 +1,45 @@
+use crate::data::{DriveMode, DriveSettings};
+
+/// Per-track saturation stage. Drive 0 is a clean bypass in every mode.
+#[derive(Default)]
+pub struct Waveshaper {
+    settings: DriveSettings,
+    // Bitcrush sample-and-hold state
+    held: f32,
+    hold_phase: f32,
+}
+
+impl Waveshaper {
+    pub fn set(&mut self, settings: DriveSettings) {
+        self.settings = settings;
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        let drive = self.settings.drive.clamp(0.0, 1.0);
+        if drive <= 0.0 {
+            return x;
+        }
+
+        // Up to roughly +28 dB of pre-gain
+        let gain = 1.0 + drive * 24.0;
+        match self.settings.mode {
+            DriveMode::SoftClip => (x * gain).tanh(),
+            DriveMode::HardClip => (x * gain).clamp(-1.0, 1.0),
+            DriveMode::Foldback => {
+                // Triangle fold keeps the signal inside -1..1 while adding harmonics
+                let y = (x * gain + 1.0).rem_euclid(4.0);
+                if y < 2.0 { y - 1.0 } else { 3.0 - y }
+            },
+            DriveMode::Bitcrush => {
+                // Drive lowers both bit depth (16 -> 2) and sample rate (1x -> 1/32x)
+                self.hold_phase += 1.0;
+                if self.hold_phase >= 1.0 + drive * 31.0 {
+                    self.hold_phase = 0.0;
+                    let levels = 2.0_f32.powf(16.0 - drive * 14.0) / 2.0;
+                    self.held = (x * levels).round() / levels;
+                }
+                self.held
+            },
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::data::{DrumKind, DrumSettings, FilterMode, FilterSettings, Waveform};
+use super::envelope::{falloff, SILENCE};
+use super::filter::Svf;
+use super::oscillator::{Noise, Oscillator};
+
+/// Note at which a drum plays at its base pitch.
+const ROOT_NOTE: f32 = 48.0;
+const KICK_HZ: f32 = 50.0;
+const SNARE_HZ: f32 = 180.0;
+/// The six detuned square waves behind the 808 hi-hats.
+const HAT_HZ: [f32; 6] = [205.3, 304.4, 369.6, 522.7, 540.0, 800.0];
+/// Kick and snare pitch drop
+const SWEEP_SECONDS: f32 = 0.04;
+/// Noise transient on the front of the kick
+const CLICK_SECONDS: f32 = 0.005;
+/// A clap is a few quick noise bursts, the last one ringing out into the tail
+const CLAP_BURST_SECONDS: f32 = 0.01;
+const CLAP_BURSTS: u32 = 3;
+
+/// Per-voice state for the drum source. Every kind is built from the same parts: a sine
+/// body with a pitch sweep, filtered noise and a bank of square waves, each with a one-pole decay.
+#[derive(Default)]
+pub struct DrumVoice {
+    body: Oscillator,
+    metal: [Oscillator; 6],
+    noise: Noise,
+    filter: Svf,
+    /// Main level; the drum is finished once this falls silent
+    amp: f32,
+    amp_coef: f32,
+    /// Snare body, which dies away faster than its noise
+    body_level: f32,
+    body_coef: f32,
+    sweep: f32,
+    sweep_coef: f32,
+    click: f32,
+    click_coef: f32,
+    burst: f32,
+    burst_coef: f32,
+    burst_len: u32,
+    elapsed: u32,
+}
+
+impl DrumVoice {
+    pub fn note_on(&mut self, settings: &DrumSettings, sample_rate: f32) {
+        self.body.reset();
+        for osc in self.metal.iter_mut() {
+            osc.reset();
+        }
+        let decay = settings.decay.max(0.001);
+        self.amp = 1.0;
+        self.amp_coef = falloff(decay, sample_rate);
+        self.body_level = 1.0;
+        self.body_coef = falloff(decay * 0.5, sample_rate);
+        self.sweep = 1.0;
+        self.sweep_coef = falloff(SWEEP_SECONDS, sample_rate);
+        self.click = 1.0;
+        self.click_coef = falloff(CLICK_SECONDS, sample_rate);
+        self.burst = 1.0;
+        self.burst_coef = falloff(CLAP_BURST_SECONDS, sample_rate);
+        self.burst_len = ((CLAP_BURST_SECONDS * sample_rate) as u32).max(1);
+        self.elapsed = 0;
+
+        // Tone thins the hats and moves the clap's band up; the snare's noise is always thinned below ~900 Hz
+        let tone = settings.tone.clamp(0.0, 1.0);
+        let (mode, cutoff, resonance) = match settings.kind {
+            DrumKind::Kick => (FilterMode::LowPass, 1.0, 0.0),
+            DrumKind::Snare => (FilterMode::HighPass, 0.55, 0.0),
+            DrumKind::ClosedHat | DrumKind::OpenHat => (FilterMode::HighPass, 0.7 + 0.2 * tone, 0.2),
+            DrumKind::Clap => (FilterMode::BandPass, 0.55 + 0.2 * tone, 0.4),
+        };
+        self.filter = Svf::default();
+        self.filter.set(&FilterSettings { mode, cutoff, resonance }, sample_rate);
+    }
+
+    /// Next sample at `note` (fractional, so slides bend it), or `None` once the drum has decayed to silence.
+    pub fn next(&mut self, settings: &DrumSettings, note: f32, sample_rate: f32) -> Option<f32> {
+        if self.amp < SILENCE {
+            return None;
+        }
+        let pitch = 2.0_f32.powf((note - ROOT_NOTE + settings.tune) / 12.0);
+        let tone = settings.tone.clamp(0.0, 1.0);
+        let sweep = settings.sweep.clamp(0.0, 1.0);
+        let bursts_end = self.burst_len * (CLAP_BURSTS - 1);
+
+        let value = match settings.kind {
+            DrumKind::Kick => {
+                let freq = KICK_HZ * pitch * (1.0 + sweep * 6.0 * self.sweep);
+                let body = self.body.next(Waveform::Sine, freq, 0.5, sample_rate);
+                (body + self.noise.next() * self.click * tone) * self.amp
+            },
+            DrumKind::Snare => {
+                let freq = SNARE_HZ * pitch * (1.0 + sweep * self.sweep);
+                let body = self.body.next(Waveform::Sine, freq, 0.5, sample_rate) * self.body_level;
+                let noise = self.filter.process(self.noise.next()) * self.amp;
+                body * (1.0 - tone) + noise * tone
+            },
+            DrumKind::ClosedHat | DrumKind::OpenHat => {
+                let metal = self.metal.iter_mut().zip(HAT_HZ)
+                    .map(|(osc, hz)| osc.next(Waveform::Square, hz * pitch, 0.5, sample_rate))
+                    .sum::<f32>() / HAT_HZ.len() as f32;
+                self.filter.process(metal * 0.5 + self.noise.next() * 0.5) * self.amp
+            },
+            DrumKind::Clap => {
+                if self.elapsed < bursts_end && self.elapsed.is_multiple_of(self.burst_len) {
+                    self.burst = 1.0;
+                }
+                let level = if self.elapsed < bursts_end { self.burst } else { self.amp };
+                self.filter.process(self.noise.next()) * level * 2.0
+            },
+        };
+
+        self.burst *= self.burst_coef;
+        self.body_level *= self.body_coef;
+        self.sweep *= self.sweep_coef;
+        self.click *= self.click_coef;
+        // The clap's tail only starts decaying on its last burst
+        if settings.kind != DrumKind::Clap || self.elapsed >= bursts_end {
+            self.amp *= self.amp_coef;
+        }
+        self.elapsed = self.elapsed.saturating_add(1);
+        Some(value)
+    }
+}
@@ -0,0 +1,200 @@
+use crate::data::MasterSettings;
+
+/// How far ahead the limiter looks. The whole mix is delayed by this much.
+const LOOKAHEAD_SECONDS: f32 = 0.005;
+const LIMITER_RELEASE_SECONDS: f32 = 0.1;
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-6).log10()
+}
+
+/// One-pole coefficient with a time constant of `seconds`.
+fn smoothing(seconds: f32, sample_rate: f32) -> f32 {
+    (-1.0 / (seconds * sample_rate).max(1.0)).exp()
+}
+
+/// Stereo-linked feed-forward compressor, peak detected in the dB domain.
+struct Compressor {
+    envelope_db: f32,
+    attack_coef: f32,
+    release_coef: f32,
+}
+
+impl Compressor {
+    fn new() -> Self {
+        Self { envelope_db: 0.0, attack_coef: 0.0, release_coef: 0.0 }
+    }
+
+    fn set(&mut self, settings: &MasterSettings, sample_rate: f32) {
+        self.attack_coef = smoothing(settings.attack_ms.max(0.1) / 1000.0, sample_rate);
+        self.release_coef = smoothing(settings.release_ms.max(1.0) / 1000.0, sample_rate);
+    }
+
+    /// Gain reduction in dB (positive) for this frame.
+    fn process(&mut self, [left, right]: [f32; 2], settings: &MasterSettings) -> f32 {
+        let level_db = gain_to_db(left.abs().max(right.abs()));
+        let over = level_db - settings.threshold_db;
+        let target = if over > 0.0 { over * (1.0 - 1.0 / settings.ratio.max(1.0)) } else { 0.0 };
+        let coef = if target > self.envelope_db { self.attack_coef } else { self.release_coef };
+        self.envelope_db = target + (self.envelope_db - target) * coef;
+        self.envelope_db
+    }
+}
+
+/// Look-ahead peak limiter. The input is delayed by the look-ahead time while the gain
+/// computer sees it undelayed, so the gain is already down by the time a peak comes out.
+struct Limiter {
+    buffers: [Vec<f32>; 2],
+    pos: usize,
+    lookahead: usize,
+    gain: f32,
+    target: f32,
+    /// Samples left before the target may rise again
+    hold: usize,
+    attack_coef: f32,
+    release_coef: f32,
+}
+
+impl Limiter {
+    fn new(sample_rate: f32) -> Self {
+        let lookahead = ((LOOKAHEAD_SECONDS * sample_rate) as usize).max(1);
+        Self {
+            buffers: [vec![0.0; lookahead], vec![0.0; lookahead]],
+            pos: 0,
+            lookahead,
+            gain: 1.0,
+            target: 1.0,
+            hold: 0,
+            // Reaches the target well inside the look-ahead window
+            attack_coef: smoothing(LOOKAHEAD_SECONDS / 5.0, sample_rate),
+            release_coef: smoothing(LIMITER_RELEASE_SECONDS, sample_rate),
+        }
+    }
+
+    fn process(&mut self, input: [f32; 2], ceiling: f32) -> [f32; 2] {
+        let peak = input[0].abs().max(input[1].abs());
+        let wanted = if peak > ceiling { ceiling / peak } else { 1.0 };
+        if wanted <= self.target {
+            self.target = wanted;
+            self.hold = self.lookahead;
+        } else if self.hold > 0 {
+            self.hold -= 1;
+        } else {
+            self.target = wanted;
+        }
+        let coef = if self.target < self.gain { self.attack_coef } else { self.release_coef };
+        self.gain = self.target + (self.gain - self.target) * coef;
+
+        let mut output = [0.0; 2];
+        for (channel, buffer) in self.buffers.iter_mut().enumerate() {
+            let delayed = std::mem::replace(&mut buffer[self.pos], input[channel]);
+            // Anything the smoothed gain didn't quite catch is clipped at the ceiling
+            output[channel] = (delayed * self.gain).clamp(-ceiling, ceiling);
+        }
+        self.pos = (self.pos + 1) % self.lookahead;
+        output
+    }
+}
+
+/// Everything between the track mix and the output: glue compressor, then limiter.
+pub struct MasterBus {
+    settings: MasterSettings,
+    sample_rate: f32,
+    compressor: Compressor,
+    limiter: Limiter,
+    /// Deepest combined gain reduction since last taken, in dB
+    peak_reduction_db: f32,
+}
+
+impl MasterBus {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut bus = Self {
+            settings: MasterSettings::default(),
+            sample_rate,
+            compressor: Compressor::new(),
+            limiter: Limiter::new(sample_rate),
+            peak_reduction_db: 0.0,
+        };
+        bus.set(MasterSettings::default());
+        bus
+    }
+
+    pub fn set(&mut self, settings: MasterSettings) {
+        self.settings = settings;
+        self.compressor.set(&settings, self.sample_rate);
+    }
+
+    pub fn process(&mut self, input: [f32; 2]) -> [f32; 2] {
+        let settings = &self.settings;
+        let mut frame = input;
+        let mut reduction_db = 0.0;
+        if settings.compressor {
+            let compression_db = self.compressor.process(frame, settings);
+            let gain = db_to_gain(settings.makeup_db - compression_db);
+            frame = [frame[0] * gain, frame[1] * gain];
+            reduction_db += compression_db;
+        }
+        let ceiling = db_to_gain(settings.ceiling_db.min(0.0));
+        let output = self.limiter.process(frame, ceiling);
+        reduction_db -= gain_to_db(self.limiter.gain);
+        self.peak_reduction_db = self.peak_reduction_db.max(reduction_db);
+        output
+    }
+
+    /// Returns the deepest gain reduction since the last call, for the meter.
+    pub fn take_gain_reduction(&mut self) -> f32 {
+        std::mem::take(&mut self.peak_reduction_db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn limiter_gain_is_down_before_the_peak_comes_out() {
+        let mut limiter = Limiter::new(SAMPLE_RATE);
+        let ceiling = db_to_gain(-1.0);
+        // A quiet signal jumping straight to four times full scale
+        let input: Vec<f32> = (0..4800).map(|i| if i < 1000 { 0.1 } else { 4.0 }).collect();
+        for (i, &x) in input.iter().enumerate() {
+            let [left, _] = limiter.process([x, x], ceiling);
+            let delayed = i.checked_sub(limiter.lookahead).map_or(0.0, |j| input[j]);
+            // What comes out before the safety clip is already at the ceiling, give or take 0.2 dB
+            assert!(delayed * limiter.gain <= ceiling * db_to_gain(0.2));
+            assert!(left <= ceiling);
+        }
+        let [left, _] = limiter.process([4.0, 4.0], ceiling);
+        assert!(left > ceiling * db_to_gain(-0.2));
+    }
+
+    #[test]
+    fn limiter_passes_quiet_signals_through_delayed() {
+        let mut limiter = Limiter::new(SAMPLE_RATE);
+        let input: Vec<f32> = (0..1000).map(|i| 0.5 * (i as f32 * 0.05).sin()).collect();
+        for (i, &x) in input.iter().enumerate() {
+            let [left, right] = limiter.process([x, -x], 1.0);
+            let delayed = i.checked_sub(limiter.lookahead).map_or(0.0, |j| input[j]);
+            assert_eq!([left, right], [delayed, -delayed]);
+        }
+    }
+
+    #[test]
+    fn compressor_settles_at_the_ratio_above_threshold() {
+        let mut bus = MasterBus::new(SAMPLE_RATE);
+        bus.set(MasterSettings { compressor: true, threshold_db: -20.0, ratio: 4.0, makeup_db: 0.0, ceiling_db: 0.0, ..MasterSettings::default() });
+        let mut output = [0.0; 2];
+        for _ in 0..SAMPLE_RATE as usize {
+            output = bus.process([1.0, 1.0]);
+        }
+        // 20 dB over at 4:1 comes out 5 dB over, so 15 dB of reduction
+        assert!((gain_to_db(output[0]) + 15.0).abs() < 0.1);
+        assert!((bus.take_gain_reduction() - 15.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::data::EnvelopeSettings;
+
+/// Level below which a decaying segment counts as finished (-80 dB)
+pub const SILENCE: f32 = 0.0001;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum Stage {
+    #[default]
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Per-voice ADSR state machine.
+/// Attack is a linear ramp; decay and release are exponential, which is what the ear expects.
+#[derive(Default)]
+pub struct Adsr {
+    stage: Stage,
+    level: f32,
+    attack_step: f32,
+    decay_coef: f32,
+    sustain: f32,
+    release_coef: f32,
+}
+
+/// One-pole coefficient that falls to -60 dB over `seconds`.
+pub fn falloff(seconds: f32, sample_rate: f32) -> f32 {
+    let samples = (seconds * sample_rate).max(1.0);
+    (-6.907_755 / samples).exp()
+}
+
+impl Adsr {
+    pub fn note_on(&mut self, settings: &EnvelopeSettings, sample_rate: f32) {
+        self.attack_step = 1.0 / (settings.attack * sample_rate).max(1.0);
+        self.decay_coef = falloff(settings.decay, sample_rate);
+        self.sustain = settings.sustain.clamp(0.0, 1.0);
+        self.release_coef = falloff(settings.release, sample_rate);
+        // Restart from the current level so retriggers don't click
+        self.stage = Stage::Attack;
+    }
+
+    pub fn note_off(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+        }
+    }
+
+    /// Silences immediately, for sources that have run out of material.
+    pub fn kill(&mut self) {
+        self.stage = Stage::Idle;
+        self.level = 0.0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.stage != Stage::Idle
+    }
+
+    /// Active and not yet released, i.e. the key is still down.
+    pub fn is_held(&self) -> bool {
+        !matches!(self.stage, Stage::Idle | Stage::Release)
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn next(&mut self) -> f32 {
+        match self.stage {
+            Stage::Idle => {},
+            Stage::Attack => {
+                self.level += self.attack_step;
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            },
+            Stage::Decay => {
+                self.level = self.sustain + (self.level - self.sustain) * self.decay_coef;
+                if self.level - self.sustain < SILENCE {
+                    self.level = self.sustain;
+                    self.stage = if self.sustain > 0.0 { Stage::Sustain } else { Stage::Idle };
+                }
+            },
+            Stage::Sustain => {
+                self.level = self.sustain;
+            },
+            Stage::Release => {
+                self.level *= self.release_coef;
+                if self.level < SILENCE {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            },
+        }
+        self.level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 1000.0;
+
+    fn adsr(settings: EnvelopeSettings) -> Adsr {
+        let mut env = Adsr::default();
+        env.note_on(&settings, SAMPLE_RATE);
+        env
+    }
+
+    #[test]
+    fn attack_ramps_to_full_in_the_attack_time() {
+        let mut env = adsr(EnvelopeSettings { attack: 0.008, decay: 0.1, sustain: 0.5, release: 0.1 });
+        let ramp: Vec<f32> = (0..8).map(|_| env.next()).collect();
+        assert_eq!(ramp, [0.125, 0.25, 0.375, 0.5, 0.625, 0.75, 0.875, 1.0]);
+        assert!(env.is_held());
+    }
+
+    #[test]
+    fn decay_settles_on_sustain_and_release_goes_idle() {
+        let mut env = adsr(EnvelopeSettings { attack: 0.0, decay: 0.1, sustain: 0.5, release: 0.2 });
+        env.next();
+        // -60 dB of the way down after the decay time, and exactly on sustain soon after
+        for _ in 0..100 {
+            env.next();
+        }
+        assert!(env.level() - 0.5 < 0.5 * 0.0011);
+        for _ in 0..100 {
+            env.next();
+        }
+        assert_eq!(env.level(), 0.5);
+        assert!(env.is_held());
+
+        env.note_off();
+        assert!(!env.is_held());
+        assert!(env.is_active());
+        for _ in 0..200 {
+            env.next();
+        }
+        assert!(env.level() < 0.5 * 0.0011);
+        for _ in 0..100 {
+            env.next();
+        }
+        assert!(!env.is_active());
+        assert_eq!(env.level(), 0.0);
+    }
+
+    #[test]
+    fn zero_sustain_finishes_without_a_note_off() {
+        let mut env = adsr(EnvelopeSettings { attack: 0.0, decay: 0.05, sustain: 0.0, release: 1.0 });
+        for _ in 0..200 {
+            env.next();
+        }
+        assert!(!env.is_active());
+    }
+
+    #[test]
+    fn retrigger_restarts_from_the_current_level() {
+        let mut env = adsr(EnvelopeSettings { attack: 0.0, decay: 0.1, sustain: 0.5, release: 0.1 });
+        for _ in 0..200 {
+            env.next();
+        }
+        env.note_on(&EnvelopeSettings { attack: 0.008, decay: 0.1, sustain: 0.5, release: 0.1 }, SAMPLE_RATE);
+        assert_eq!(env.next(), 0.625);
+    }
+}
@@ -0,0 +1,98 @@
+use std::f32::consts::PI;
+use crate::data::{FilterMode, FilterSettings};
+
+/// Maps the 0-1 cutoff knob onto 20 Hz - 20 kHz, evenly per octave.
+pub fn cutoff_hz(normalized: f32) -> f32 {
+    20.0 * 1000.0_f32.powf(normalized.clamp(0.0, 1.0))
+}
+
+/// Topology-preserving-transform state-variable filter (Zavalishin/Simper).
+/// Stays stable under fast cutoff changes, which matters for effect and CC sweeps.
+#[derive(Default)]
+pub struct Svf {
+    mode: FilterMode,
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl Svf {
+    pub fn set(&mut self, settings: &FilterSettings, sample_rate: f32) {
+        let fc = cutoff_hz(settings.cutoff).min(sample_rate * 0.49);
+        let g = (PI * fc / sample_rate).tan();
+        // Resonance 0-1 maps damping from a gentle Q of 0.5 (k=2) down to near self-oscillation
+        self.k = 2.0 - 1.98 * settings.resonance.clamp(0.0, 1.0);
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+        self.mode = settings.mode;
+    }
+
+    pub fn process(&mut self, v0: f32) -> f32 {
+        let v3 = v0 - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        let low = v2;
+        let band = v1;
+        let high = v0 - self.k * v1 - v2;
+        match self.mode {
+            FilterMode::LowPass => low,
+            FilterMode::HighPass => high,
+            FilterMode::BandPass => band,
+            FilterMode::Notch => low + high,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    /// Knob position for a cutoff in Hz, the inverse of `cutoff_hz`.
+    fn knob(hz: f32) -> f32 {
+        (hz / 20.0).ln() / 1000.0_f32.ln()
+    }
+
+    /// Steady-state amplitude of a unit sine at `hz` through a filter at 1 kHz.
+    fn response(mode: FilterMode, hz: f32) -> f32 {
+        let mut svf = Svf::default();
+        svf.set(&FilterSettings { mode, cutoff: knob(1000.0), resonance: 0.0 }, SAMPLE_RATE);
+        let output: Vec<f32> = (0..SAMPLE_RATE as usize).map(|i| svf.process((i as f32 * hz / SAMPLE_RATE * TAU).sin())).collect();
+        let settled = &output[output.len() / 2..];
+        (settled.iter().map(|v| v * v).sum::<f32>() / settled.len() as f32).sqrt() * 2.0_f32.sqrt()
+    }
+
+    #[test]
+    fn cutoff_knob_spans_the_audible_range() {
+        assert_eq!(cutoff_hz(0.0), 20.0);
+        assert!((cutoff_hz(1.0) - 20000.0).abs() < 1.0);
+        assert!((cutoff_hz(knob(1000.0)) - 1000.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn low_and_high_pass_split_at_the_cutoff() {
+        assert!(response(FilterMode::LowPass, 100.0) > 0.95);
+        assert!(response(FilterMode::LowPass, 10000.0) < 0.02);
+        assert!(response(FilterMode::HighPass, 100.0) < 0.02);
+        assert!(response(FilterMode::HighPass, 10000.0) > 0.95);
+    }
+
+    #[test]
+    fn band_pass_peaks_and_notch_cuts_at_the_cutoff() {
+        let centre = response(FilterMode::BandPass, 1000.0);
+        assert!(centre > 5.0 * response(FilterMode::BandPass, 100.0));
+        assert!(centre > 5.0 * response(FilterMode::BandPass, 10000.0));
+        assert!(response(FilterMode::Notch, 1000.0) < 0.01);
+        assert!(response(FilterMode::Notch, 100.0) > 0.95);
+        assert!(response(FilterMode::Notch, 10000.0) > 0.95);
+    }
+}
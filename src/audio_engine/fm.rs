@@ -0,0 +1,129 @@
+use std::f32::consts::TAU;
+
+use crate::data::FmSettings;
+use super::envelope::Adsr;
+
+/// Modulation index at a modulator level of 1.0, in radians.
+const MAX_INDEX: f32 = 8.0;
+
+#[derive(Default)]
+struct Operator {
+    phase: f32,
+    envelope: Adsr,
+    /// Last two outputs, averaged for feedback to keep it from turning to noise
+    history: [f32; 2],
+}
+
+/// Per-voice state for the four-operator FM source.
+#[derive(Default)]
+pub struct FmVoice {
+    ops: [Operator; 4],
+}
+
+impl FmVoice {
+    pub fn note_on(&mut self, settings: &FmSettings, sample_rate: f32) {
+        for (op, params) in self.ops.iter_mut().zip(settings.operators.iter()) {
+            if !op.envelope.is_active() {
+                op.phase = 0.0;
+                op.history = [0.0; 2];
+            }
+            op.envelope.note_on(&params.envelope, sample_rate);
+        }
+    }
+
+    pub fn note_off(&mut self) {
+        for op in self.ops.iter_mut() {
+            op.envelope.note_off();
+        }
+    }
+
+    pub fn next(&mut self, settings: &FmSettings, freq: f32, sample_rate: f32) -> f32 {
+        let algorithm = settings.algorithm;
+        let modulators = algorithm.modulators();
+        let carriers = algorithm.carriers();
+        let count = algorithm.operator_count();
+        let top = count - 1;
+
+        // Modulators always have higher numbers than what they modulate, so work downwards
+        let mut outputs = [0.0_f32; 4];
+        for i in (0..count).rev() {
+            let params = &settings.operators[i];
+            let op = &mut self.ops[i];
+
+            let mut modulation: f32 = (0..count)
+                .filter(|j| modulators[i] & (1 << j) != 0)
+                .map(|j| outputs[j] * MAX_INDEX)
+                .sum();
+            if i == top {
+                modulation += (op.history[0] + op.history[1]) * 0.5 * settings.feedback.clamp(0.0, 1.0) * MAX_INDEX * 0.5;
+            }
+
+            let out = (op.phase * TAU + modulation).sin() * params.level.clamp(0.0, 1.0) * op.envelope.next();
+            op.history = [out, op.history[0]];
+            outputs[i] = out;
+
+            op.phase += freq * params.ratio.max(0.0) / sample_rate;
+            op.phase -= op.phase.floor();
+        }
+
+        let carrier_count = carriers.count_ones().max(1) as f32;
+        (0..count).filter(|i| carriers & (1 << i) != 0).map(|i| outputs[i]).sum::<f32>() / carrier_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{EnvelopeSettings, FmAlgorithm, FmOperator};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    /// 100 samples per cycle
+    const FREQ: f32 = 480.0;
+
+    /// Operators that sit at full level from the first sample.
+    fn settings(algorithm: FmAlgorithm, levels: [f32; 4], feedback: f32) -> FmSettings {
+        let envelope = EnvelopeSettings { attack: 0.0, decay: 0.1, sustain: 1.0, release: 0.1 };
+        let op = |level: f32| FmOperator { ratio: 1.0, level, envelope };
+        FmSettings { algorithm, feedback, operators: levels.map(op) }
+    }
+
+    fn render(settings: &FmSettings, len: usize) -> Vec<f32> {
+        let mut voice = FmVoice::default();
+        voice.note_on(settings, SAMPLE_RATE);
+        (0..len).map(|_| voice.next(settings, FREQ, SAMPLE_RATE)).collect()
+    }
+
+    #[test]
+    fn unmodulated_carrier_is_a_sine_at_the_note() {
+        let output = render(&settings(FmAlgorithm::TwoOp, [1.0, 0.0, 0.0, 0.0], 0.0), 1000);
+        for (i, v) in output.iter().enumerate() {
+            assert!((v - (i as f32 * FREQ / SAMPLE_RATE * TAU).sin()).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn integer_ratio_modulation_keeps_the_period() {
+        let fm = settings(FmAlgorithm::TwoOp, [1.0, 0.5, 0.0, 0.0], 0.0);
+        let output = render(&fm, 1000);
+        let sine = render(&settings(FmAlgorithm::TwoOp, [1.0, 0.0, 0.0, 0.0], 0.0), 1000);
+        assert!(output.iter().zip(&sine).any(|(a, b)| (a - b).abs() > 0.5));
+        for i in 0..900 {
+            assert!((output[i] - output[i + 100]).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn two_op_ignores_operators_three_and_four() {
+        let two = render(&settings(FmAlgorithm::TwoOp, [1.0, 0.5, 0.0, 0.0], 0.3), 500);
+        let extra = render(&settings(FmAlgorithm::TwoOp, [1.0, 0.5, 1.0, 1.0], 0.3), 500);
+        assert_eq!(two, extra);
+    }
+
+    #[test]
+    fn carriers_are_averaged_to_stay_within_full_scale() {
+        for algorithm in FmAlgorithm::ALL {
+            let output = render(&settings(algorithm, [1.0; 4], 1.0), 2000);
+            assert!(output.iter().all(|v| v.abs() <= 1.0), "{:?}", algorithm);
+        }
+    }
+}
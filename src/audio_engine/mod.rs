@@ -0,0 +1,440 @@
+use bevy::prelude::*;
+use crossbeam_channel::{unbounded, Receiver};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample};
+use rtrb::{Consumer, Producer, PushError, RingBuffer};
+use assert_no_alloc::{assert_no_alloc, permit_alloc};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use midir::{MidiInput, Ignore};
+
+mod bus;
+mod delay;
+mod distortion;
+mod drum;
+mod dynamics;
+mod envelope;
+mod filter;
+mod fm;
+mod modulation;
+mod oscillator;
+mod params;
+mod reverb;
+mod sampler;
+mod sequencer;
+mod synth;
+mod wavetable;
+
+use crate::data::{AuxBusSettings, InstrumentSettings, MasterSettings, PlayMode, VoiceSettings};
+use sequencer::{Sequencer, SequencerEvent};
+use synth::Synth;
+pub use filter::cutoff_hz;
+pub use params::SharedParams;
+pub use sampler::SampleData;
+pub use sequencer::Song;
+pub use wavetable::{load_cycle, WavetableData};
+
+// --- API ---
+
+#[derive(Event, Debug, Clone)]
+pub struct MidiInputEvent {
+    pub note: u8,
+    pub velocity: u8,
+    pub status: u8, // 144 = On, 128 = Off, 176 = CC (note = controller, velocity = value)
+}
+
+// Instrument definitions travel inline: a boxed one would have to be freed on the audio thread
+#[allow(clippy::large_enum_variant)]
+pub enum AudioCommand {
+    PlayNote {
+        note: u8,
+        instrument: u8,
+        velocity: u8,
+        channel: usize,
+    },
+    /// Releases `note` on the channel, or everything it holds when `note` is `None`.
+    StopNote {
+        channel: usize,
+        note: Option<u8>,
+    },
+    /// Replaces a whole instrument definition in the engine's bank.
+    SetInstrument {
+        index: usize,
+        instrument: InstrumentSettings,
+    },
+    /// Decoded sample for a sampler instrument; `None` unloads it.
+    SetSample {
+        instrument: usize,
+        sample: Option<Arc<SampleData>>,
+    },
+    /// Mip-mapped tables for a wavetable instrument; `None` clears them.
+    SetWavetable {
+        instrument: usize,
+        wavetable: Option<Arc<WavetableData>>,
+    },
+    SetVoices {
+        track: usize,
+        voices: VoiceSettings,
+    },
+    SetSend {
+        track: usize,
+        bus: usize,
+        amount: f32,
+    },
+    SetAuxBus {
+        bus: usize,
+        settings: AuxBusSettings,
+    },
+    SetMaster(MasterSettings),
+    SetPan {
+        track: usize,
+        pan: f32,
+    },
+    SetBpm(f32),
+    SetSpeed(u32),
+    /// `position` and `pattern` are where the editor is, for the sequencer to follow
+    /// pattern edits that moved things under the play head.
+    LoadSong {
+        song: Box<Song>,
+        position: usize,
+        pattern: usize,
+    },
+    SetPlayMode(PlayMode),
+    /// Song mode starts from order `position`, pattern mode loops `pattern`.
+    Play {
+        position: usize,
+        pattern: usize,
+        row: usize,
+    },
+    Stop,
+    /// Tracks were removed or moved. Voices and pattern effect state belong to a track by
+    /// position, so everything is released and cleared rather than left on the wrong track.
+    ResetTracks,
+}
+
+/// Sent back from the audio thread so the UI can follow the sequencer.
+pub enum AudioEvent {
+    Position {
+        position: usize,
+        pattern: usize,
+        row: usize,
+    },
+    NoteOn {
+        channel: usize,
+        note: u8,
+    },
+    /// Fxx changed the speed or the tempo
+    Tempo {
+        bpm: f32,
+        speed: u32,
+    },
+}
+
+/// UI-side handle to the audio thread.
+///
+/// The audio callback owns all of its state; the only links to it are bounded
+/// single-producer/single-consumer rings and the atomics in `params`. The mutexes
+/// here only serialize Bevy systems against each other and are never touched by
+/// the audio thread.
+#[derive(Resource)]
+pub struct AudioEngine {
+    commands: Mutex<Producer<AudioCommand>>,
+    events: Mutex<Consumer<AudioEvent>>,
+    garbage: Mutex<Consumer<Garbage>>,
+    pub params: Arc<SharedParams>,
+}
+
+impl AudioEngine {
+    /// Queues a command for the audio thread. Returns `false` if the ring is full
+    /// (or the audio thread never started) and the command was dropped.
+    pub fn send(&self, cmd: AudioCommand) -> bool {
+        self.commands.lock().unwrap().push(cmd).is_ok()
+    }
+
+    pub fn try_recv_event(&self) -> Option<AudioEvent> {
+        self.events.lock().unwrap().pop().ok()
+    }
+
+    /// Frees songs, samples and wavetables the audio thread has finished with.
+    pub fn collect_garbage(&self) {
+        let mut retired = self.garbage.lock().unwrap();
+        while retired.pop().is_ok() {}
+    }
+}
+
+#[derive(Resource)]
+struct MidiReceiver(Receiver<MidiInputEvent>);
+
+pub struct AudioPlugin;
+
+impl Plugin for AudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MidiInputEvent>()
+           .add_systems(Startup, setup_audio)
+           .add_systems(Update, dispatch_midi_events);
+    }
+}
+
+// --- Implementation ---
+
+const COMMAND_CAPACITY: usize = 1024;
+const EVENT_CAPACITY: usize = 1024;
+const GARBAGE_CAPACITY: usize = 64;
+
+/// Heap objects the audio thread is done with. Dropping them there could free memory
+/// in the callback, so they travel back to the UI thread instead.
+enum Garbage {
+    Song(#[allow(dead_code)] Box<Song>),
+    Sample(#[allow(dead_code)] Arc<SampleData>),
+    Wavetable(#[allow(dead_code)] Arc<WavetableData>),
+}
+
+struct AudioState {
+    synth: Synth,
+    sequencer: Sequencer,
+    events: Producer<AudioEvent>,
+    garbage: Producer<Garbage>,
+    params: Arc<SharedParams>,
+}
+
+impl AudioState {
+    fn handle_command(&mut self, cmd: AudioCommand) {
+        match cmd {
+            AudioCommand::PlayNote { note, channel, velocity, instrument } => {
+                self.synth.note_on(channel, note, instrument, velocity, 0);
+            },
+            AudioCommand::StopNote { channel, note } => self.synth.note_off(channel, note),
+            AudioCommand::SetInstrument { index, instrument } => self.synth.set_instrument(index, instrument),
+            AudioCommand::SetSample { instrument, sample } => {
+                if let Some(old) = self.synth.set_sample(instrument, sample) {
+                    self.retire(Garbage::Sample(old));
+                }
+            },
+            AudioCommand::SetWavetable { instrument, wavetable } => {
+                if let Some(old) = self.synth.set_wavetable(instrument, wavetable) {
+                    self.retire(Garbage::Wavetable(old));
+                }
+            },
+            AudioCommand::SetVoices { track, voices } => self.synth.set_voices(track, voices),
+            AudioCommand::SetSend { track, bus, amount } => self.synth.set_send(track, bus, amount),
+            AudioCommand::SetAuxBus { bus, settings } => self.synth.set_aux_bus(bus, settings),
+            AudioCommand::SetMaster(master) => self.synth.set_master(master),
+            AudioCommand::SetPan { track, pan } => self.synth.set_pan(track, pan),
+            AudioCommand::SetBpm(bpm) => self.sequencer.set_bpm(bpm),
+            AudioCommand::SetSpeed(speed) => self.sequencer.set_speed(speed),
+            AudioCommand::LoadSong { song, position, pattern } => {
+                let old = self.sequencer.load_song(song, position, pattern);
+                self.retire(Garbage::Song(old));
+            },
+            AudioCommand::SetPlayMode(mode) => self.sequencer.set_mode(mode),
+            AudioCommand::Play { position, pattern, row } => self.sequencer.play(position, pattern, row),
+            AudioCommand::Stop => {
+                self.sequencer.stop();
+                self.synth.release_all();
+            },
+            AudioCommand::ResetTracks => {
+                self.sequencer.reset_channels();
+                self.synth.release_all();
+            },
+        }
+    }
+
+    /// Hands a heap object back to the UI thread to be freed there.
+    fn retire(&mut self, garbage: Garbage) {
+        if let Err(PushError::Full(garbage)) = self.garbage.push(garbage) {
+            // Only if the UI thread stopped collecting; freeing here is the lesser evil
+            permit_alloc(|| drop(garbage));
+        }
+    }
+
+    /// Runs the sequencer clock for one frame and renders it as a stereo pair.
+    fn render_frame(&mut self) -> [f32; 2] {
+        let AudioState { synth, sequencer, events, .. } = self;
+        sequencer.advance(synth.sample_rate(), |event| match event {
+            SequencerEvent::RowStarted { position, pattern, row } => {
+                let _ = events.push(AudioEvent::Position { position, pattern, row });
+            },
+            SequencerEvent::NoteOn { channel, note, instrument, velocity, sample_offset } => {
+                synth.note_on(channel, note, instrument, velocity, sample_offset);
+                let _ = events.push(AudioEvent::NoteOn { channel, note });
+            },
+            SequencerEvent::NoteOff { channel } => synth.note_off(channel, None),
+            SequencerEvent::FilterCutoff { channel, cutoff } => synth.set_filter_cutoff(channel, cutoff),
+            SequencerEvent::FilterResonance { channel, resonance } => synth.set_filter_resonance(channel, resonance),
+            SequencerEvent::Pan { channel, pan } => synth.set_pattern_pan(channel, pan),
+            SequencerEvent::Pitch { channel, semitones } => synth.set_pattern_pitch(channel, semitones),
+            SequencerEvent::Volume { channel, gain } => synth.set_pattern_gain(channel, gain),
+            SequencerEvent::Tempo { bpm, speed } => {
+                let _ = events.push(AudioEvent::Tempo { bpm, speed });
+            },
+        });
+        synth.set_tempo(sequencer.bpm());
+
+        synth.render()
+    }
+
+    /// Renders interleaved frames in whatever channel layout the device asked for.
+    /// Mono gets the L/R average, extra channels beyond stereo are left silent.
+    fn render<T: Sample + FromSample<f32>>(&mut self, data: &mut [T], channels: usize) {
+        for frame in data.chunks_mut(channels) {
+            let [left, right] = self.render_frame();
+            match frame {
+                [mono] => *mono = T::from_sample((left + right) * 0.5),
+                [l, r, rest @ ..] => {
+                    *l = T::from_sample(left);
+                    *r = T::from_sample(right);
+                    rest.fill(T::EQUILIBRIUM);
+                },
+                [] => {},
+            }
+        }
+    }
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut state: AudioState,
+    mut commands: Consumer<AudioCommand>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let channels = config.channels as usize;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            // Debug builds report any allocation made in here (see main.rs)
+            assert_no_alloc(|| {
+                let started = Instant::now();
+
+                // Process Commands
+                while let Ok(cmd) = commands.pop() {
+                    state.handle_command(cmd);
+                }
+
+                // Render Audio
+                state.render(data, channels);
+
+                let frames = data.len() / channels.max(1);
+                let budget = frames as f32 / state.synth.sample_rate();
+                if budget > 0.0 {
+                    state.params.dsp_load.store(started.elapsed().as_secs_f32() / budget);
+                }
+                state.params.gain_reduction_db.store(state.synth.take_gain_reduction());
+            })
+        },
+        |err| eprintln!("Audio Stream Error: {}", err),
+        None,
+    )
+}
+
+fn setup_audio(mut commands: Commands) {
+    // 1. Channels
+    let (command_tx, command_rx) = RingBuffer::<AudioCommand>::new(COMMAND_CAPACITY);
+    let (event_tx, event_rx) = RingBuffer::<AudioEvent>::new(EVENT_CAPACITY);
+    let (garbage_tx, garbage_rx) = RingBuffer::<Garbage>::new(GARBAGE_CAPACITY);
+    let (midi_tx, midi_rx) = unbounded::<MidiInputEvent>();
+    let params = Arc::new(SharedParams::default());
+
+    commands.insert_resource(AudioEngine {
+        commands: Mutex::new(command_tx),
+        events: Mutex::new(event_rx),
+        garbage: Mutex::new(garbage_rx),
+        params: params.clone(),
+    });
+    commands.insert_resource(MidiReceiver(midi_rx));
+
+    // 2. Audio Thread (CPAL)
+    std::thread::spawn(move || {
+        let host = cpal::default_host();
+        let device = match host.default_output_device() {
+            Some(d) => d,
+            None => {
+                eprintln!("ERROR: No default audio output device found!");
+                return;
+            }
+        };
+
+        let supported = match device.default_output_config() {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("ERROR: Failed to get audio config: {}", e);
+                return;
+            }
+        };
+        let sample_format = supported.sample_format();
+        let config: cpal::StreamConfig = supported.into();
+        println!("Audio output: {} Hz, {} channel(s), {:?}", config.sample_rate.0, config.channels, sample_format);
+
+        let state = AudioState {
+            synth: Synth::new(config.sample_rate.0 as f32),
+            sequencer: Sequencer::new(params.clone()),
+            events: event_tx,
+            garbage: garbage_tx,
+            params,
+        };
+
+        let stream_result = match sample_format {
+            SampleFormat::I8 => build_stream::<i8>(&device, &config, state, command_rx),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, state, command_rx),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, state, command_rx),
+            SampleFormat::U8 => build_stream::<u8>(&device, &config, state, command_rx),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, state, command_rx),
+            SampleFormat::U32 => build_stream::<u32>(&device, &config, state, command_rx),
+            SampleFormat::F64 => build_stream::<f64>(&device, &config, state, command_rx),
+            _ => build_stream::<f32>(&device, &config, state, command_rx),
+        };
+
+        if let Ok(stream) = stream_result {
+            if let Err(e) = stream.play() {
+                eprintln!("ERROR: Failed to play audio stream: {}", e);
+            }
+            std::thread::park(); // Keep audio thread alive
+        } else {
+             eprintln!("ERROR: Failed to build audio stream: {}", stream_result.err().unwrap());
+        }
+    });
+
+    // 3. MIDI Thread (Midir)
+    std::thread::spawn(move || {
+        let midi_in_result = MidiInput::new("Terminal Velocity Input");
+        match midi_in_result {
+            Ok(mut midi_in) => {
+                midi_in.ignore(Ignore::None);
+                
+                let ports = midi_in.ports();
+                if let Some(port) = ports.first() {
+                    println!("Connecting to MIDI port: {}", midi_in.port_name(port).unwrap_or_default());
+                    
+                    let _conn = midi_in.connect(port, "tv-input-conn", move |_, message, _| {
+                        if message.len() >= 3 {
+                             let status = message[0] & 0xF0;
+                             if status == 0x90 || status == 0x80 || status == 0xB0 {
+                                  let _ = midi_tx.send(MidiInputEvent {
+                                      status,
+                                      note: message[1],
+                                      velocity: message[2],
+                                  });
+                             }
+                        }
+                    }, ());
+                    
+                    // Keep connection alive
+                    loop { std::thread::sleep(std::time::Duration::from_secs(1)); }
+                } else {
+                    println!("No MIDI ports found.");
+                }
+            },
+            Err(e) => {
+                eprintln!("ERROR: Failed to initialize MIDI Input: {}", e);
+            }
+        }
+    });
+}
+
+fn dispatch_midi_events(
+    mut events: EventWriter<MidiInputEvent>,
+    receiver: Res<MidiReceiver>,
+) {
+    while let Ok(event) = receiver.0.try_recv() {
+        events.send(event);
+    }
+}
@@ -0,0 +1,147 @@
+use std::f32::consts::TAU;
+
+use crate::data::{LfoSettings, LfoShape, ModDestination, ModSource, ModulationSettings};
+use super::envelope::Adsr;
+use super::oscillator::Noise;
+
+/// Time since the engine started, in seconds and in beats at the tempo as it changed.
+/// Free-running LFOs read their phase from here so every voice stays in step.
+pub struct LfoClock {
+    seconds: f64,
+    beats: f64,
+    bpm: f32,
+}
+
+impl Default for LfoClock {
+    fn default() -> Self {
+        Self { seconds: 0.0, beats: 0.0, bpm: 120.0 }
+    }
+}
+
+impl LfoClock {
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.bpm = bpm;
+    }
+
+    pub fn advance(&mut self, sample_rate: f32) {
+        self.seconds += 1.0 / sample_rate as f64;
+        self.beats += self.bpm as f64 / 60.0 / sample_rate as f64;
+    }
+
+    /// Cycles per second of an LFO at the current tempo.
+    fn rate(&self, settings: &LfoSettings) -> f32 {
+        if settings.sync {
+            self.bpm / 60.0 / settings.division.beats()
+        } else {
+            settings.rate.max(0.0)
+        }
+    }
+
+    /// Where a free-running LFO is in its cycle right now.
+    fn phase(&self, settings: &LfoSettings) -> f32 {
+        let cycles = if settings.sync {
+            self.beats / settings.division.beats() as f64
+        } else {
+            self.seconds * settings.rate.max(0.0) as f64
+        };
+        cycles.fract() as f32
+    }
+}
+
+#[derive(Default)]
+struct Lfo {
+    phase: f32,
+    /// Current sample-and-hold step
+    held: f32,
+    noise: Noise,
+}
+
+impl Lfo {
+    fn note_on(&mut self, settings: &LfoSettings, clock: &LfoClock) {
+        self.phase = if settings.retrigger { 0.0 } else { clock.phase(settings) };
+        self.held = self.noise.next();
+    }
+
+    fn next(&mut self, settings: &LfoSettings, clock: &LfoClock, sample_rate: f32) -> f32 {
+        let t = self.phase;
+        let value = match settings.shape {
+            LfoShape::Sine => (t * TAU).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (t - 0.5).abs(),
+            LfoShape::Saw => 2.0 * t - 1.0,
+            LfoShape::Square => if t < 0.5 { 1.0 } else { -1.0 },
+            LfoShape::SampleHold => self.held,
+        };
+
+        self.phase += clock.rate(settings) / sample_rate;
+        if self.phase >= 1.0 {
+            self.phase -= self.phase.floor();
+            self.held = self.noise.next();
+        }
+        value
+    }
+}
+
+/// What the matrix adds to each destination for one sample: pitch in semitones,
+/// cutoff and pulse width in knob units, pan from -1 to 1 and volume as a gain offset.
+#[derive(Default, Clone, Copy)]
+pub struct ModValues {
+    pub pitch: f32,
+    pub cutoff: f32,
+    pub pulse_width: f32,
+    pub pan: f32,
+    pub volume: f32,
+}
+
+/// Per-voice modulation state: both LFOs, the modulation envelope and what the note was played with.
+#[derive(Default)]
+pub struct Modulation {
+    lfos: [Lfo; 2],
+    envelope: Adsr,
+    velocity: f32,
+    note: f32,
+}
+
+impl Modulation {
+    pub fn note_on(&mut self, settings: &ModulationSettings, note: u8, velocity: u8, clock: &LfoClock, sample_rate: f32) {
+        for (lfo, lfo_settings) in self.lfos.iter_mut().zip(settings.lfos.iter()) {
+            lfo.note_on(lfo_settings, clock);
+        }
+        self.envelope.note_on(&settings.envelope, sample_rate);
+        self.velocity = velocity as f32 / 127.0;
+        self.note = ((note as f32 - 60.0) / 60.0).clamp(-1.0, 1.0);
+    }
+
+    pub fn note_off(&mut self) {
+        self.envelope.note_off();
+    }
+
+    /// Runs the LFOs and envelope for one sample and sums the matrix. `amp` is the
+    /// level of the voice's own amplitude envelope.
+    pub fn next(&mut self, settings: &ModulationSettings, amp: f32, clock: &LfoClock, sample_rate: f32) -> ModValues {
+        let lfo1 = self.lfos[0].next(&settings.lfos[0], clock, sample_rate);
+        let lfo2 = self.lfos[1].next(&settings.lfos[1], clock, sample_rate);
+        let envelope = self.envelope.next();
+
+        let mut values = ModValues::default();
+        for route in settings.routes.iter() {
+            let source = match route.source {
+                ModSource::Off => continue,
+                ModSource::Lfo1 => lfo1,
+                ModSource::Lfo2 => lfo2,
+                ModSource::AmpEnvelope => amp,
+                ModSource::ModEnvelope => envelope,
+                ModSource::Velocity => self.velocity,
+                ModSource::Note => self.note,
+            };
+            let amount = source * route.amount.clamp(-1.0, 1.0);
+            match route.destination {
+                ModDestination::Pitch => values.pitch += amount * 12.0,
+                ModDestination::Cutoff => values.cutoff += amount,
+                ModDestination::PulseWidth => values.pulse_width += amount * 0.5,
+                ModDestination::Pan => values.pan += amount,
+                ModDestination::Volume => values.volume += amount,
+            }
+        }
+        values
+    }
+}
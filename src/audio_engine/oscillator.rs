@@ -0,0 +1,75 @@
+use std::f32::consts::TAU;
+
+use crate::data::Waveform;
+
+/// Polynomial band-limited step residual. `t` is the phase (0-1), `dt` the phase increment.
+/// Subtracting this around each discontinuity removes most of the aliasing a naive edge makes.
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Phase-accumulating oscillator with PolyBLEP-corrected edges.
+#[derive(Default)]
+pub struct Oscillator {
+    phase: f32,
+}
+
+impl Oscillator {
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    pub fn next(&mut self, waveform: Waveform, freq: f32, pulse_width: f32, sample_rate: f32) -> f32 {
+        let dt = (freq / sample_rate).min(0.5);
+        let t = self.phase;
+
+        let value = match waveform {
+            Waveform::Sine => (t * TAU).sin(),
+            Waveform::Saw => 2.0 * t - 1.0 - poly_blep(t, dt),
+            Waveform::Square => pulse(t, dt, 0.5),
+            Waveform::Pulse => pulse(t, dt, pulse_width),
+        };
+
+        self.phase += dt;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+        value
+    }
+}
+
+/// Band-limited pulse: a rising edge at 0 and a falling edge at `width`.
+fn pulse(t: f32, dt: f32, width: f32) -> f32 {
+    // Keep both edges at least one sample apart so they can't cancel into silence
+    let width = width.clamp(dt, 1.0 - dt);
+    let naive = if t < width { 1.0 } else { -1.0 };
+    naive + poly_blep(t, dt) - poly_blep((t - width).rem_euclid(1.0), dt)
+}
+
+/// xorshift32 white noise: cheap, and nothing to allocate on the audio thread.
+pub struct Noise(u32);
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self(0x9E37_79B9)
+    }
+}
+
+impl Noise {
+    pub fn next(&mut self) -> f32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use crate::data::MAX_TRACKS;
+
+/// An `f32` stored as raw bits so it can be shared without a lock.
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Mixer values for one track. Written by the UI, read by the sequencer on every row.
+pub struct TrackParams {
+    pub volume: AtomicF32,
+    pub muted: AtomicBool,
+    pub instrument: AtomicU8,
+}
+
+impl Default for TrackParams {
+    fn default() -> Self {
+        Self {
+            volume: AtomicF32::new(1.0),
+            muted: AtomicBool::new(false),
+            instrument: AtomicU8::new(0),
+        }
+    }
+}
+
+impl TrackParams {
+    pub fn set(&self, volume: f32, muted: bool, instrument: u8) {
+        self.volume.store(volume);
+        self.muted.store(muted, Ordering::Relaxed);
+        self.instrument.store(instrument, Ordering::Relaxed);
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn instrument(&self) -> u8 {
+        self.instrument.load(Ordering::Relaxed)
+    }
+}
+
+/// Lock-free parameter snapshot shared between the UI and the audio callback.
+/// Each field is independently atomic; nothing here ever blocks either side.
+pub struct SharedParams {
+    pub tracks: [TrackParams; MAX_TRACKS],
+    /// Fraction of the buffer period the last callback spent rendering (0.0 - 1.0+)
+    pub dsp_load: AtomicF32,
+    /// Deepest master bus gain reduction during the last callback, in dB
+    pub gain_reduction_db: AtomicF32,
+}
+
+impl Default for SharedParams {
+    fn default() -> Self {
+        Self {
+            tracks: std::array::from_fn(|_| TrackParams::default()),
+            dsp_load: AtomicF32::new(0.0),
+            gain_reduction_db: AtomicF32::new(0.0),
+        }
+    }
+}
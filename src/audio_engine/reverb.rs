@@ -0,0 +1,133 @@
+use crate::data::ReverbSettings;
+
+// Freeverb tunings (in samples at 44.1 kHz), scaled to the device rate at construction
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+const FIXED_GAIN: f32 = 0.015;
+const MAX_PRE_DELAY_MS: f32 = 200.0;
+
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Self { buffer: vec![0.0; len.max(1)], pos: 0, filter_store: 0.0 }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damp: f32) -> f32 {
+        let output = self.buffer[self.pos];
+        self.filter_store = output * (1.0 - damp) + self.filter_store * damp;
+        self.buffer[self.pos] = input + self.filter_store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+
+    fn clear(&mut self) {
+        self.buffer.fill(0.0);
+        self.filter_store = 0.0;
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Self { buffer: vec![0.0; len.max(1)], pos: 0 }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.buffer[self.pos];
+        self.buffer[self.pos] = input + buffered * 0.5;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        buffered - input
+    }
+
+    fn clear(&mut self) {
+        self.buffer.fill(0.0);
+    }
+}
+
+/// Freeverb-style stereo reverb on an aux bus, shared by all tracks through their sends.
+/// One instance per bus keeps the cost flat no matter how many tracks feed it.
+pub struct Reverb {
+    settings: ReverbSettings,
+    sample_rate: f32,
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+    pre_delay: Vec<f32>,
+    pre_delay_pos: usize,
+    pre_delay_samples: usize,
+}
+
+impl Reverb {
+    pub fn new(sample_rate: f32) -> Self {
+        let scale = |len: usize| (len as f32 * sample_rate / 44100.0) as usize;
+        let side = |spread: usize| {
+            (
+                COMB_TUNINGS.iter().map(|&len| Comb::new(scale(len + spread))).collect(),
+                ALLPASS_TUNINGS.iter().map(|&len| Allpass::new(scale(len + spread))).collect(),
+            )
+        };
+        let (combs_l, allpasses_l) = side(0);
+        let (combs_r, allpasses_r) = side(STEREO_SPREAD);
+
+        let mut reverb = Self {
+            settings: ReverbSettings::default(),
+            sample_rate,
+            combs: [combs_l, combs_r],
+            allpasses: [allpasses_l, allpasses_r],
+            pre_delay: vec![0.0; (MAX_PRE_DELAY_MS * 0.001 * sample_rate) as usize + 1],
+            pre_delay_pos: 0,
+            pre_delay_samples: 0,
+        };
+        reverb.set(ReverbSettings::default());
+        reverb
+    }
+
+    pub fn set(&mut self, settings: ReverbSettings) {
+        self.settings = settings;
+        let samples = settings.pre_delay_ms.clamp(0.0, MAX_PRE_DELAY_MS) * 0.001 * self.sample_rate;
+        self.pre_delay_samples = (samples as usize).min(self.pre_delay.len() - 1);
+    }
+
+    /// Silences the tail without touching the settings.
+    pub fn clear(&mut self) {
+        self.combs.iter_mut().flatten().for_each(Comb::clear);
+        self.allpasses.iter_mut().flatten().for_each(Allpass::clear);
+        self.pre_delay.fill(0.0);
+    }
+
+    /// Takes the summed send signal and returns the wet stereo return.
+    pub fn process(&mut self, input: f32) -> [f32; 2] {
+        // Pre-delay
+        let len = self.pre_delay.len();
+        self.pre_delay[self.pre_delay_pos] = input;
+        let read_pos = (self.pre_delay_pos + len - self.pre_delay_samples) % len;
+        let delayed = self.pre_delay[read_pos];
+        self.pre_delay_pos = (self.pre_delay_pos + 1) % len;
+
+        let feedback = 0.7 + self.settings.size.clamp(0.0, 1.0) * 0.28;
+        let damp = self.settings.damping.clamp(0.0, 1.0) * 0.4;
+        let input = delayed * FIXED_GAIN;
+
+        let mut out = [0.0; 2];
+        for (side, value) in out.iter_mut().enumerate() {
+            let mut sum = 0.0;
+            for comb in self.combs[side].iter_mut() {
+                sum += comb.process(input, feedback, damp);
+            }
+            for allpass in self.allpasses[side].iter_mut() {
+                sum = allpass.process(sum);
+            }
+            *value = sum * self.settings.wet.clamp(0.0, 1.0) * 3.0;
+        }
+        out
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::data::{LoopMode, SamplerSettings};
+
+/// A decoded sample, mixed down to mono at the file's own rate.
+/// Decoded on the UI side and shared with the audio thread behind an `Arc`.
+pub struct SampleData {
+    frames: Vec<f32>,
+    sample_rate: f32,
+}
+
+#[derive(Debug)]
+pub enum SampleError {
+    Wav(hound::Error),
+    Flac(claxon::Error),
+    Empty,
+}
+
+impl fmt::Display for SampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleError::Wav(e) => write!(f, "WAV: {}", e),
+            SampleError::Flac(e) => write!(f, "FLAC: {}", e),
+            SampleError::Empty => write!(f, "file contains no audio"),
+        }
+    }
+}
+
+impl SampleData {
+    /// Decodes a WAV or FLAC file (chosen by extension).
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SampleError> {
+        let path = path.as_ref();
+        let is_flac = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("flac"));
+        let sample = if is_flac { Self::load_flac(path)? } else { Self::load_wav(path)? };
+        if sample.frames.is_empty() {
+            return Err(SampleError::Empty);
+        }
+        Ok(sample)
+    }
+
+    fn load_wav(path: &Path) -> Result<Self, SampleError> {
+        let reader = hound::WavReader::open(path).map_err(SampleError::Wav)?;
+        let spec = reader.spec();
+        let samples: Vec<f32> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>(),
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader.into_samples::<i32>().map(|s| s.map(|s| s as f32 * scale)).collect::<Result<_, _>>()
+            },
+        }
+        .map_err(SampleError::Wav)?;
+        Ok(Self::from_interleaved(&samples, spec.channels as usize, spec.sample_rate))
+    }
+
+    fn load_flac(path: &Path) -> Result<Self, SampleError> {
+        let mut reader = claxon::FlacReader::open(path).map_err(SampleError::Flac)?;
+        let info = reader.streaminfo();
+        let scale = 1.0 / (1_i64 << (info.bits_per_sample - 1)) as f32;
+        let samples: Vec<f32> = reader.samples().map(|s| s.map(|s| s as f32 * scale)).collect::<Result<_, _>>()
+            .map_err(SampleError::Flac)?;
+        Ok(Self::from_interleaved(&samples, info.channels as usize, info.sample_rate))
+    }
+
+    fn from_interleaved(samples: &[f32], channels: usize, sample_rate: u32) -> Self {
+        let channels = channels.max(1);
+        let frames = samples.chunks(channels).map(|frame| frame.iter().sum::<f32>() / channels as f32).collect();
+        Self { frames, sample_rate: sample_rate as f32 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn frames(&self) -> &[f32] {
+        &self.frames
+    }
+
+    /// 4-point cubic Hermite read at a fractional frame position.
+    fn read(&self, position: f64) -> f32 {
+        let last = self.frames.len() as isize - 1;
+        let index = position.floor() as isize;
+        let frac = (position - index as f64) as f32;
+        let at = |i: isize| self.frames[i.clamp(0, last) as usize];
+        let (y0, y1, y2, y3) = (at(index - 1), at(index), at(index + 1), at(index + 2));
+
+        let c1 = 0.5 * (y2 - y0);
+        let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+        let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+        ((c3 * frac + c2) * frac + c1) * frac + y1
+    }
+}
+
+/// Per-voice playback position within a `SampleData`.
+#[derive(Default)]
+pub struct SamplePlayer {
+    position: f64,
+    reverse: bool,
+}
+
+impl SamplePlayer {
+    /// Starts playback `offset` frames in. Past the end of the sample, the note is silent.
+    pub fn start(&mut self, offset: usize) {
+        self.position = offset as f64;
+        self.reverse = false;
+    }
+
+    /// Next output value at `note`'s pitch (fractional, so slides bend it), or `None` once a
+    /// non-looping sample has played out.
+    pub fn next(&mut self, sample: &SampleData, settings: &SamplerSettings, note: f32, sample_rate: f32) -> Option<f32> {
+        let len = sample.len();
+        if self.position >= len as f64 || self.position < 0.0 {
+            return None;
+        }
+        let value = sample.read(self.position);
+
+        let semitones = note - settings.root_note as f32 + settings.fine_tune / 100.0;
+        let step = (sample.sample_rate / sample_rate * 2.0_f32.powf(semitones / 12.0)) as f64;
+
+        // Loop end 0 means the end of the sample
+        let end = if settings.loop_end == 0 { len } else { (settings.loop_end as usize).min(len) };
+        let start = (settings.loop_start as usize).min(end.saturating_sub(1));
+        let (start, end) = (start as f64, end as f64);
+        let looping = !settings.one_shot && settings.loop_mode != LoopMode::Off && end - start >= 1.0;
+
+        if self.reverse {
+            self.position -= step;
+            if self.position < start {
+                self.position = (2.0 * start - self.position).min(end - 1.0);
+                self.reverse = false;
+            }
+        } else {
+            self.position += step;
+            if looping && self.position >= end {
+                match settings.loop_mode {
+                    LoopMode::PingPong => {
+                        self.position = (2.0 * end - self.position).max(start);
+                        self.reverse = true;
+                    },
+                    _ => self.position = start + (self.position - start) % (end - start),
+                }
+            }
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    const OUTPUT_RATE: f32 = 44100.0;
+
+    /// One second of a 441 Hz sine recorded at half the output rate.
+    fn sine() -> SampleData {
+        let rate = OUTPUT_RATE / 2.0;
+        SampleData { frames: (0..rate as usize).map(|i| (i as f32 * 441.0 / rate * TAU).sin()).collect(), sample_rate: rate }
+    }
+
+    /// Plays the whole sample at `note` and measures the frequency from its zero crossings.
+    fn frequency(sample: &SampleData, settings: &SamplerSettings, note: f32) -> f32 {
+        let mut player = SamplePlayer::default();
+        player.start(0);
+        let output: Vec<f32> = std::iter::from_fn(|| player.next(sample, settings, note, OUTPUT_RATE)).collect();
+        let crossings = output.windows(2).filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0)).count();
+        crossings as f32 / 2.0 / (output.len() as f32 / OUTPUT_RATE)
+    }
+
+    #[test]
+    fn root_note_plays_at_the_recorded_pitch() {
+        let sample = sine();
+        let settings = SamplerSettings::default();
+        let root = settings.root_note as f32;
+        assert!((frequency(&sample, &settings, root) - 441.0).abs() < 2.0);
+        assert!((frequency(&sample, &settings, root + 12.0) - 882.0).abs() < 4.0);
+        assert!((frequency(&sample, &settings, root - 12.0) - 220.5).abs() < 1.0);
+
+        let sharp = SamplerSettings { fine_tune: 100.0, ..settings };
+        let semitone_up = 441.0 * 2.0_f32.powf(1.0 / 12.0);
+        assert!((frequency(&sample, &sharp, root) - semitone_up).abs() < 2.0);
+    }
+
+    #[test]
+    fn interpolation_hits_frames_and_follows_a_ramp() {
+        let sample = SampleData { frames: vec![0.0, 0.3, -0.2, 0.9, 0.1], sample_rate: OUTPUT_RATE };
+        for (i, &frame) in sample.frames.iter().enumerate() {
+            assert_eq!(sample.read(i as f64), frame);
+        }
+
+        let ramp = SampleData { frames: (0..8).map(|i| i as f32).collect(), sample_rate: OUTPUT_RATE };
+        for position in [1.25, 2.5, 4.75] {
+            assert!((ramp.read(position) - position as f32).abs() < 1e-5);
+        }
+    }
+}
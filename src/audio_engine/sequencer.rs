@@ -0,0 +1,920 @@
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use crate::data::{break_row, effect_to_pan, ChannelData, EffectType, ExtendedEffect, Pattern, PlayMode, FILTER_RESONANCE_BASE, NOTE_OFF, MAX_TRACKS, SPEED_TEMPO_SPLIT};
+use super::params::SharedParams;
+
+/// Snapshot of the pattern data the sequencer plays.
+/// Built on the UI side and handed to the audio thread whenever it changes;
+/// the replaced snapshot is sent back so it is freed off the audio thread.
+#[derive(Clone, PartialEq, Default)]
+pub struct Song {
+    pub patterns: Vec<Pattern>,
+    /// Pattern indices in play order
+    pub order: Vec<usize>,
+}
+
+/// Something the sequencer wants the engine to do. Emitted from `advance`.
+pub enum SequencerEvent {
+    RowStarted {
+        position: usize,
+        pattern: usize,
+        row: usize,
+    },
+    NoteOn {
+        channel: usize,
+        note: u8,
+        instrument: u8,
+        velocity: u8,
+        /// Frames into the sample to start from (9xx)
+        sample_offset: usize,
+    },
+    NoteOff {
+        channel: usize,
+    },
+    FilterCutoff {
+        channel: usize,
+        cutoff: f32,
+    },
+    FilterResonance {
+        channel: usize,
+        resonance: f32,
+    },
+    Pan {
+        channel: usize,
+        pan: f32,
+    },
+    /// Offset from the played note, in semitones, from slides, portamento, arpeggio and vibrato
+    Pitch {
+        channel: usize,
+        semitones: f32,
+    },
+    /// Gain from the volume column, volume slides, tremolo and note cuts (0-1). Notes are
+    /// triggered as if at full volume, so this is all there is of the column.
+    Volume {
+        channel: usize,
+        gain: f32,
+    },
+    /// Fxx changed the speed or the tempo
+    Tempo {
+        bpm: f32,
+        speed: u32,
+    },
+}
+
+/// Volume column scale: notes default to, and volume slides top out at, this.
+const MAX_VOLUME: f32 = 64.0;
+/// Slides move 1/16 semitone per unit per tick, like FastTracker's linear frequency mode.
+const SLIDE_UNIT: f32 = 1.0 / 16.0;
+/// How far slides can carry the pitch from the note, in semitones.
+const SLIDE_RANGE: f32 = 96.0;
+/// Vibrato and tremolo phase steps per cycle; the speed digit is added every tick.
+const WAVE_STEPS: f32 = 64.0;
+/// Vibrato depth per unit, in semitones
+const VIBRATO_UNIT: f32 = 1.0 / 8.0;
+/// Tremolo depth per unit, on the volume scale
+const TREMOLO_UNIT: f32 = 4.0;
+/// 9xx moves the sample start in steps of this many frames
+const SAMPLE_OFFSET_UNIT: usize = 256;
+
+/// Phase and parameters of a vibrato or tremolo. Either digit left at zero keeps its last value.
+#[derive(Clone, Copy, Default)]
+struct Wobble {
+    phase: f32,
+    speed: u8,
+    depth: u8,
+}
+
+impl Wobble {
+    fn set(&mut self, value: u8) {
+        if value >> 4 > 0 {
+            self.speed = value >> 4;
+        }
+        if value & 0x0F > 0 {
+            self.depth = value & 0x0F;
+        }
+    }
+
+    /// Steps the phase on and returns the new offset in depth units.
+    fn next(&mut self) -> f32 {
+        self.phase = (self.phase + self.speed as f32) % WAVE_STEPS;
+        (self.phase / WAVE_STEPS * TAU).sin() * self.depth as f32
+    }
+}
+
+/// Effect state for one channel, carried from tick to tick and row to row.
+#[derive(Clone, Copy)]
+struct ChannelState {
+    /// The effect on the current row
+    effect: Option<(EffectType, u8)>,
+    /// The note sounding on the channel, for portamento and retrigger
+    note: Option<u8>,
+    instrument: u8,
+    velocity: u8,
+    /// A note held back by EDx until its tick comes
+    delayed: Option<ChannelData>,
+    /// Where pitch slides and portamento have got to
+    slide: f32,
+    /// Where tone portamento is heading, relative to the sounding note
+    portamento_target: f32,
+    /// This tick's arpeggio step and vibrato offset
+    arpeggio: f32,
+    vibrato: f32,
+    volume: f32,
+    /// This tick's tremolo offset
+    tremolo: f32,
+    vibrato_wave: Wobble,
+    tremolo_wave: Wobble,
+    // A zero parameter reuses the last one
+    slide_up_memory: u8,
+    slide_down_memory: u8,
+    volume_slide_memory: u8,
+    portamento_memory: u8,
+    offset_memory: u8,
+    /// E60 row and how many E6x repeats are left
+    loop_row: usize,
+    loop_count: u8,
+    /// Last pitch and gain sent, so only changes go out
+    sent_pitch: f32,
+    sent_gain: f32,
+}
+
+impl Default for ChannelState {
+    fn default() -> Self {
+        Self {
+            effect: None,
+            note: None,
+            instrument: 0,
+            velocity: 0,
+            delayed: None,
+            slide: 0.0,
+            portamento_target: 0.0,
+            arpeggio: 0.0,
+            vibrato: 0.0,
+            volume: MAX_VOLUME,
+            tremolo: 0.0,
+            vibrato_wave: Wobble::default(),
+            tremolo_wave: Wobble::default(),
+            slide_up_memory: 0,
+            slide_down_memory: 0,
+            volume_slide_memory: 0,
+            portamento_memory: 0,
+            offset_memory: 0,
+            loop_row: 0,
+            loop_count: 0,
+            sent_pitch: 0.0,
+            sent_gain: 1.0,
+        }
+    }
+}
+
+impl ChannelState {
+    fn gain(&self) -> f32 {
+        (self.volume + self.tremolo).clamp(0.0, MAX_VOLUME) / MAX_VOLUME
+    }
+
+    fn slide_by(&mut self, semitones: f32) {
+        self.slide = (self.slide + semitones).clamp(-SLIDE_RANGE, SLIDE_RANGE);
+    }
+
+    fn volume_by(&mut self, amount: f32) {
+        self.volume = (self.volume + amount).clamp(0.0, MAX_VOLUME);
+    }
+
+    /// Runs the row's continuous effect for one tick after the first.
+    fn tick(&mut self, tick: u32) {
+        let Some((effect, value)) = self.effect else { return };
+        match effect {
+            EffectType::Arpeggio => {
+                let step = match tick % 3 {
+                    0 => 0,
+                    1 => value >> 4,
+                    _ => value & 0x0F,
+                };
+                self.arpeggio = step as f32;
+            },
+            EffectType::PitchSlideUp => self.slide_by(self.slide_up_memory as f32 * SLIDE_UNIT),
+            EffectType::PitchSlideDown => self.slide_by(-(self.slide_down_memory as f32) * SLIDE_UNIT),
+            EffectType::TonePortamento => {
+                let step = self.portamento_memory as f32 * SLIDE_UNIT;
+                self.slide += (self.portamento_target - self.slide).clamp(-step, step);
+            },
+            EffectType::Vibrato => self.vibrato = self.vibrato_wave.next() * VIBRATO_UNIT,
+            EffectType::Tremolo => self.tremolo = self.tremolo_wave.next() * TREMOLO_UNIT,
+            EffectType::VolumeSlide => {
+                let (up, down) = (self.volume_slide_memory >> 4, self.volume_slide_memory & 0x0F);
+                // Up wins when both are set
+                let delta = if up > 0 { up as f32 } else { -(down as f32) };
+                self.volume_by(delta);
+            },
+            EffectType::Extended => {
+                if let Some((ExtendedEffect::NoteCut, cut_tick)) = ExtendedEffect::parse(value) {
+                    if tick == cut_tick as u32 {
+                        self.volume = 0.0;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Sends the channel's pitch and gain if either moved.
+    fn emit(&mut self, channel: usize, on_event: &mut impl FnMut(SequencerEvent)) {
+        let semitones = self.slide + self.arpeggio + self.vibrato;
+        if semitones != self.sent_pitch {
+            on_event(SequencerEvent::Pitch { channel, semitones });
+            self.sent_pitch = semitones;
+        }
+        let gain = self.gain();
+        if gain != self.sent_gain {
+            on_event(SequencerEvent::Volume { channel, gain });
+            self.sent_gain = gain;
+        }
+    }
+}
+
+/// Row/tick clock running inside the audio callback.
+/// Time is counted in output samples, so row starts land on exact sample frames.
+pub struct Sequencer {
+    song: Box<Song>,
+    params: Arc<SharedParams>,
+    playing: bool,
+    mode: PlayMode,
+    /// Position in the order list; only moves in song mode
+    position: usize,
+    pattern: usize,
+    row: usize,
+    tick: u32,
+    speed: u32,
+    bpm: f32,
+    samples_to_next_tick: f64,
+    channels: [ChannelState; MAX_TRACKS],
+    // Where to go once the current row ends, from Bxx, Dxx and E6x
+    jump_position: Option<usize>,
+    jump_row: Option<usize>,
+    loop_row: Option<usize>,
+}
+
+impl Sequencer {
+    pub fn new(params: Arc<SharedParams>) -> Self {
+        Self {
+            song: Box::default(),
+            params,
+            playing: false,
+            mode: PlayMode::default(),
+            position: 0,
+            pattern: 0,
+            row: 0,
+            tick: 0,
+            speed: 6,
+            bpm: 120.0,
+            samples_to_next_tick: 0.0,
+            channels: [ChannelState::default(); MAX_TRACKS],
+            jump_position: None,
+            jump_row: None,
+            loop_row: None,
+        }
+    }
+
+    /// Swaps in a new song and hands back the old one for disposal. Patterns may have been
+    /// added, deleted or moved since the last one: pattern mode goes on with `pattern`, the one
+    /// the editor now has open, and song mode stays at its position (so it follows remapped
+    /// order entries) unless the order list changed length, when it goes to `position`.
+    pub fn load_song(&mut self, song: Box<Song>, position: usize, pattern: usize) -> Box<Song> {
+        let old = std::mem::replace(&mut self.song, song);
+        match self.mode {
+            PlayMode::Pattern => self.pattern = pattern,
+            PlayMode::Song => {
+                if self.song.order.len() != old.order.len() {
+                    self.position = position;
+                }
+                if self.position >= self.song.order.len() {
+                    self.position = 0;
+                }
+                if let Some(&pattern) = self.song.order.get(self.position) {
+                    self.pattern = pattern;
+                }
+            },
+        }
+        if self.pattern >= self.song.patterns.len() {
+            self.pattern = 0;
+            self.row = 0;
+        } else if self.row >= self.rows() {
+            // The pattern was shortened under the play head: carry on from where it now ends
+            self.go_to(self.position + 1, 0);
+        }
+        old
+    }
+
+    /// Starts at `row`: of the pattern at order `position` in song mode, of `pattern` in pattern mode.
+    pub fn play(&mut self, position: usize, pattern: usize, row: usize) {
+        self.playing = true;
+        self.position = position;
+        self.pattern = match self.mode {
+            PlayMode::Song => self.song.order.get(position).copied().unwrap_or(pattern),
+            PlayMode::Pattern => pattern,
+        };
+        self.row = row;
+        self.tick = 0;
+        // Fire the first row on the very next sample
+        self.samples_to_next_tick = 0.0;
+        // The engine drops pattern pitch and volume on stop, so start from scratch too
+        self.reset_channels();
+        self.jump_position = None;
+        self.jump_row = None;
+        self.loop_row = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    /// Forgets every channel's notes, slides, volumes and effect memories.
+    pub fn reset_channels(&mut self) {
+        self.channels = [ChannelState::default(); MAX_TRACKS];
+    }
+
+    /// Switching mode mid-song carries on from the pattern that's playing.
+    pub fn set_mode(&mut self, mode: PlayMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_bpm(&mut self, bpm: f32) {
+        self.bpm = bpm.max(1.0);
+    }
+
+    pub fn bpm(&self) -> f32 {
+        self.bpm
+    }
+
+    /// Ticks per row, which is also how many times per row tick effects run.
+    pub fn set_speed(&mut self, speed: u32) {
+        self.speed = speed.max(1);
+    }
+
+    /// Standard Tracker Formula: Time = 2.5 / BPM per tick
+    fn samples_per_tick(&self, sample_rate: f32) -> f64 {
+        sample_rate as f64 * 2.5 / self.bpm as f64
+    }
+
+    /// Advances the clock by one sample frame, running a tick when one is due.
+    pub fn advance(&mut self, sample_rate: f32, mut on_event: impl FnMut(SequencerEvent)) {
+        if !self.playing {
+            return;
+        }
+
+        if self.samples_to_next_tick <= 0.0 {
+            self.process_tick(&mut on_event);
+            self.samples_to_next_tick += self.samples_per_tick(sample_rate);
+        }
+        self.samples_to_next_tick -= 1.0;
+    }
+
+    fn process_tick(&mut self, on_event: &mut impl FnMut(SequencerEvent)) {
+        if self.tick == 0 {
+            self.trigger_row(on_event);
+        } else {
+            for ch_idx in 0..MAX_TRACKS {
+                self.run_tick(ch_idx, on_event);
+            }
+        }
+
+        self.tick += 1;
+        if self.tick >= self.speed {
+            self.tick = 0;
+            self.next_row();
+        }
+    }
+
+    /// Moves to the next row, or wherever a jump on the row just played points.
+    fn next_row(&mut self) {
+        if let Some(row) = self.loop_row.take() {
+            // A pattern loop wins over any jump on the same row
+            self.jump_position = None;
+            self.jump_row = None;
+            self.row = row;
+        } else if self.jump_position.is_some() || self.jump_row.is_some() {
+            let row = self.jump_row.take().unwrap_or(0);
+            match self.jump_position.take() {
+                Some(position) => self.go_to(position, row),
+                None => self.go_to(self.position + 1, row),
+            }
+            for channel in self.channels.iter_mut() {
+                channel.loop_row = 0;
+            }
+        } else {
+            self.row += 1;
+            if self.row >= self.rows() {
+                self.go_to(self.position + 1, 0);
+            }
+        }
+
+        if self.row >= self.rows() {
+            self.row = 0;
+        }
+    }
+
+    fn rows(&self) -> usize {
+        self.song.patterns.get(self.pattern).map_or(0, |p| p.rows.len())
+    }
+
+    /// Goes to `row` of the pattern at order `position`, wrapping to the start of the
+    /// song past its end. Pattern mode stays on its pattern.
+    fn go_to(&mut self, position: usize, row: usize) {
+        self.row = row;
+        if self.mode == PlayMode::Pattern || self.song.order.is_empty() {
+            return;
+        }
+        self.position = if position < self.song.order.len() { position } else { 0 };
+        self.pattern = self.song.order[self.position];
+    }
+
+    fn trigger_row(&mut self, on_event: &mut impl FnMut(SequencerEvent)) {
+        on_event(SequencerEvent::RowStarted { position: self.position, pattern: self.pattern, row: self.row });
+
+        // Cells are copied out one at a time: the row can't stay borrowed while channels update
+        for ch_idx in 0..MAX_TRACKS {
+            let cell = self.song.patterns.get(self.pattern)
+                .and_then(|p| p.rows.get(self.row))
+                .and_then(|row| row.channels.get(ch_idx))
+                .copied();
+            let Some(cell) = cell else {
+                break;
+            };
+            self.trigger_cell(ch_idx, &cell, on_event);
+        }
+    }
+
+    /// Tick 0 for one channel: row effects, then the note, then the one-shot fine effects.
+    fn trigger_cell(&mut self, ch_idx: usize, cell: &ChannelData, on_event: &mut impl FnMut(SequencerEvent)) {
+        let value = cell.effect_value.unwrap_or(0);
+        let extended = match cell.effect {
+            Some(EffectType::Extended) => ExtendedEffect::parse(value),
+            _ => None,
+        };
+        let channel = &mut self.channels[ch_idx];
+        channel.effect = cell.effect.map(|effect| (effect, value));
+        channel.delayed = None;
+        channel.arpeggio = 0.0;
+        // Vibrato and tremolo only hold their offset while the rows keep them going
+        if cell.effect != Some(EffectType::Vibrato) {
+            channel.vibrato = 0.0;
+        }
+        if cell.effect != Some(EffectType::Tremolo) {
+            channel.tremolo = 0.0;
+        }
+
+        // Effects go first so a filter or pan command applies to the note on the same row
+        match (cell.effect, cell.effect_value) {
+            (Some(EffectType::FilterControl), Some(value)) => {
+                if value < FILTER_RESONANCE_BASE {
+                    on_event(SequencerEvent::FilterCutoff { channel: ch_idx, cutoff: value as f32 / 127.0 });
+                } else {
+                    let resonance = (value - FILTER_RESONANCE_BASE).min(0x0F) as f32 / 15.0;
+                    on_event(SequencerEvent::FilterResonance { channel: ch_idx, resonance });
+                }
+            },
+            (Some(EffectType::Panning), Some(value)) => {
+                on_event(SequencerEvent::Pan { channel: ch_idx, pan: effect_to_pan(value) });
+            },
+            // Tick effects only remember their parameter here and run from the next tick
+            (Some(EffectType::PitchSlideUp), _) if value > 0 => channel.slide_up_memory = value,
+            (Some(EffectType::PitchSlideDown), _) if value > 0 => channel.slide_down_memory = value,
+            (Some(EffectType::VolumeSlide), _) if value > 0 => channel.volume_slide_memory = value,
+            (Some(EffectType::TonePortamento), _) if value > 0 => channel.portamento_memory = value,
+            (Some(EffectType::SampleOffset), _) if value > 0 => channel.offset_memory = value,
+            (Some(EffectType::Vibrato), _) => channel.vibrato_wave.set(value),
+            (Some(EffectType::Tremolo), _) => channel.tremolo_wave.set(value),
+            (Some(EffectType::SetSpeed), _) if value > 0 => {
+                if value < SPEED_TEMPO_SPLIT {
+                    self.speed = value as u32;
+                } else {
+                    self.bpm = value as f32;
+                }
+                on_event(SequencerEvent::Tempo { bpm: self.bpm, speed: self.speed });
+            },
+            (Some(EffectType::PositionJump), _) => self.jump_position = Some(value as usize),
+            (Some(EffectType::PatternBreak), _) => self.jump_row = Some(break_row(value)),
+            _ => {},
+        }
+
+        match extended {
+            Some((ExtendedEffect::PatternLoop, 0)) => channel.loop_row = self.row,
+            Some((ExtendedEffect::PatternLoop, count)) => {
+                if channel.loop_count == 0 {
+                    channel.loop_count = count;
+                } else {
+                    channel.loop_count -= 1;
+                }
+                if channel.loop_count > 0 {
+                    self.loop_row = Some(channel.loop_row);
+                }
+            },
+            Some((ExtendedEffect::NoteDelay, delay)) if delay > 0 && cell.note.is_some() => {
+                channel.delayed = Some(*cell);
+            },
+            _ => {},
+        }
+
+        if channel.delayed.is_none() {
+            self.play_note(ch_idx, cell, on_event);
+        }
+
+        let channel = &mut self.channels[ch_idx];
+        match extended {
+            Some((ExtendedEffect::FineSlideUp, amount)) => channel.slide_by(amount as f32 * SLIDE_UNIT),
+            Some((ExtendedEffect::FineSlideDown, amount)) => channel.slide_by(-(amount as f32) * SLIDE_UNIT),
+            Some((ExtendedEffect::FineVolumeUp, amount)) => channel.volume_by(amount as f32),
+            Some((ExtendedEffect::FineVolumeDown, amount)) => channel.volume_by(-(amount as f32)),
+            Some((ExtendedEffect::NoteCut, 0)) => channel.volume = 0.0,
+            _ => {},
+        }
+        channel.emit(ch_idx, on_event);
+    }
+
+    /// Ticks after the first: continuous effects, retriggers and delayed notes.
+    fn run_tick(&mut self, ch_idx: usize, on_event: &mut impl FnMut(SequencerEvent)) {
+        let tick = self.tick;
+        let channel = &mut self.channels[ch_idx];
+        channel.tick(tick);
+
+        let extended = match channel.effect {
+            Some((EffectType::Extended, value)) => ExtendedEffect::parse(value),
+            _ => None,
+        };
+        match extended {
+            Some((ExtendedEffect::NoteDelay, delay)) if tick == delay as u32 => {
+                if let Some(cell) = channel.delayed.take() {
+                    self.play_note(ch_idx, &cell, on_event);
+                }
+            },
+            Some((ExtendedEffect::Retrigger, every)) if every > 0 && tick.is_multiple_of(every as u32) => {
+                let muted = self.params.tracks.get(ch_idx).is_some_and(|t| t.muted());
+                if let (Some(note), false) = (channel.note, muted) {
+                    on_event(SequencerEvent::NoteOn {
+                        channel: ch_idx,
+                        note,
+                        instrument: channel.instrument,
+                        velocity: channel.velocity,
+                        sample_offset: 0,
+                    });
+                }
+            },
+            _ => {},
+        }
+        self.channels[ch_idx].emit(ch_idx, on_event);
+    }
+
+    /// Plays the note column of a cell: a note off, a new note, or a portamento target.
+    fn play_note(&mut self, ch_idx: usize, cell: &ChannelData, on_event: &mut impl FnMut(SequencerEvent)) {
+        let Some(note) = cell.note else { return };
+        if note == NOTE_OFF {
+            on_event(SequencerEvent::NoteOff { channel: ch_idx });
+            return;
+        }
+        let track = self.params.tracks.get(ch_idx);
+        if track.is_some_and(|t| t.muted()) {
+            return;
+        }
+
+        let channel = &mut self.channels[ch_idx];
+        // Tone portamento glides the sounding note towards this one instead of restarting it
+        if cell.effect == Some(EffectType::TonePortamento) {
+            if let Some(sounding) = channel.note {
+                channel.portamento_target = note as f32 - sounding as f32;
+                return;
+            }
+        }
+
+        // The instrument column wins; empty cells fall back to the track's instrument
+        let instrument = cell.instrument.or(track.map(|t| t.instrument())).unwrap_or(0);
+        // The volume column goes through the channel gain, so slides can bring up a note
+        // entered at 0; the note itself plays at what full volume used to
+        let vol = cell.volume.unwrap_or(64);
+        let track_vol = track.map_or(1.0, |t| t.volume.load());
+        let velocity = ((MAX_VOLUME * track_vol) as u8).min(127);
+
+        // A new note starts from its own pitch and volume
+        channel.note = Some(note);
+        channel.instrument = instrument;
+        channel.velocity = velocity;
+        channel.slide = 0.0;
+        channel.portamento_target = 0.0;
+        channel.volume = (vol as f32).min(MAX_VOLUME);
+        channel.vibrato_wave.phase = 0.0;
+        channel.tremolo_wave.phase = 0.0;
+
+        let sample_offset = match cell.effect {
+            Some(EffectType::SampleOffset) => channel.offset_memory as usize * SAMPLE_OFFSET_UNIT,
+            _ => 0,
+        };
+        on_event(SequencerEvent::NoteOn {
+            channel: ch_idx,
+            note,
+            instrument,
+            velocity,
+            sample_offset,
+        });
+        // After the note, so the reset pitch and volume land on it rather than the one before
+        channel.emit(ch_idx, on_event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// At this rate and tempo a tick is exactly one sample, so with speed 1 every
+    /// `advance` plays one row.
+    const SAMPLE_RATE: f32 = 50.0;
+    const BPM: f32 = 125.0;
+
+    /// One-track patterns of the given lengths, played in `order`.
+    fn song(lengths: &[usize], order: &[usize]) -> Box<Song> {
+        let patterns = lengths.iter().map(|&rows| {
+            let mut pattern = Pattern::new(1);
+            pattern.resize(rows);
+            pattern
+        }).collect();
+        Box::new(Song { patterns, order: order.to_vec() })
+    }
+
+    fn set_effect(song: &mut Song, pattern: usize, row: usize, effect: EffectType, value: u8) {
+        let cell = &mut song.patterns[pattern].rows[row].channels[0];
+        cell.effect = Some(effect);
+        cell.effect_value = Some(value);
+    }
+
+    fn set_note(song: &mut Song, pattern: usize, row: usize, note: u8) {
+        song.patterns[pattern].rows[row].channels[0].note = Some(note);
+    }
+
+    fn sequencer(song: Box<Song>, mode: PlayMode) -> Sequencer {
+        let mut sequencer = Sequencer::new(Arc::new(SharedParams::default()));
+        sequencer.set_mode(mode);
+        sequencer.set_bpm(BPM);
+        sequencer.set_speed(1);
+        sequencer.load_song(song, 0, 0);
+        sequencer
+    }
+
+    /// The (position, pattern, row) of each of the next `count` rows played.
+    fn positions(sequencer: &mut Sequencer, count: usize) -> Vec<(usize, usize, usize)> {
+        let mut started = Vec::new();
+        for _ in 0..count {
+            sequencer.advance(SAMPLE_RATE, |event| {
+                if let SequencerEvent::RowStarted { position, pattern, row } = event {
+                    started.push((position, pattern, row));
+                }
+            });
+        }
+        started
+    }
+
+    /// What the synth hears from the track on one tick.
+    #[derive(Debug, PartialEq)]
+    struct Tick {
+        /// A note started on this tick, and its sample offset
+        note_on: Option<(u8, usize)>,
+        /// Pitch offset and gain once the tick has run
+        pitch: f32,
+        gain: f32,
+    }
+
+    /// Runs the next `count` ticks; with the test tempo each `advance` is one tick.
+    fn ticks(sequencer: &mut Sequencer, count: usize) -> Vec<Tick> {
+        let (mut pitch, mut gain) = (0.0, 1.0);
+        (0..count).map(|_| {
+            let mut note_on = None;
+            sequencer.advance(SAMPLE_RATE, |event| match event {
+                SequencerEvent::NoteOn { note, sample_offset, .. } => note_on = Some((note, sample_offset)),
+                SequencerEvent::Pitch { semitones, .. } => pitch = semitones,
+                SequencerEvent::Volume { gain: value, .. } => gain = value,
+                _ => {},
+            });
+            Tick { note_on, pitch, gain }
+        }).collect()
+    }
+
+    /// A one-pattern song played from the top at `speed` ticks per row.
+    fn play(song: Box<Song>, speed: u32) -> Sequencer {
+        let mut sequencer = sequencer(song, PlayMode::Pattern);
+        sequencer.set_speed(speed);
+        sequencer.play(0, 0, 0);
+        sequencer
+    }
+
+    /// Where a 4xy or 7xy wave is `tick` ticks after its row started, in depth units.
+    fn wobble(speed: u8, depth: u8, tick: u32) -> f32 {
+        ((speed as u32 * tick) as f32 / WAVE_STEPS * TAU).sin() * depth as f32
+    }
+
+    #[test]
+    fn extended_effects_parse_their_sub_command_and_parameter() {
+        for effect in ExtendedEffect::ALL {
+            assert_eq!(ExtendedEffect::parse(effect.digit() << 4 | 0x05), Some((effect, 0x05)));
+        }
+        assert_eq!(ExtendedEffect::parse(0x30), None);
+        assert_eq!(ExtendedEffect::parse(0xF1), None);
+    }
+
+    #[test]
+    fn vibrato_moves_the_pitch_from_the_second_tick() {
+        let mut song = song(&[2], &[0]);
+        set_note(&mut song, 0, 0, 60);
+        set_effect(&mut song, 0, 0, EffectType::Vibrato, 0x84);
+        let mut sequencer = play(song, 4);
+
+        let pitches: Vec<f32> = ticks(&mut sequencer, 5).iter().map(|tick| tick.pitch).collect();
+        let expected: Vec<f32> = [0.0, wobble(8, 4, 1) * VIBRATO_UNIT, wobble(8, 4, 2) * VIBRATO_UNIT, wobble(8, 4, 3) * VIBRATO_UNIT, 0.0].into();
+        assert_eq!(pitches, expected);
+    }
+
+    #[test]
+    fn tremolo_moves_the_volume_column_level() {
+        let mut song = song(&[2], &[0]);
+        set_note(&mut song, 0, 0, 60);
+        song.patterns[0].rows[0].channels[0].volume = Some(32);
+        set_effect(&mut song, 0, 0, EffectType::Tremolo, 0x84);
+        let mut sequencer = play(song, 4);
+
+        let gains: Vec<f32> = ticks(&mut sequencer, 5).iter().map(|tick| tick.gain).collect();
+        let level = |tick| (32.0 + wobble(8, 4, tick) * TREMOLO_UNIT) / MAX_VOLUME;
+        assert_eq!(gains, [0.5, level(1), level(2), level(3), 0.5]);
+    }
+
+    #[test]
+    fn tone_portamento_glides_to_the_note_without_retriggering() {
+        let mut song = song(&[2], &[0]);
+        set_note(&mut song, 0, 0, 60);
+        set_note(&mut song, 0, 1, 62);
+        set_effect(&mut song, 0, 1, EffectType::TonePortamento, 0x10);
+        let mut sequencer = play(song, 4);
+
+        let ticks = ticks(&mut sequencer, 8);
+        assert_eq!(ticks[0].note_on, Some((60, 0)));
+        assert!(ticks[1..].iter().all(|tick| tick.note_on.is_none()));
+        let pitches: Vec<f32> = ticks[4..].iter().map(|tick| tick.pitch).collect();
+        assert_eq!(pitches, [0.0, 1.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn fine_pitch_slides_move_once_on_the_first_tick() {
+        let mut song = song(&[2], &[0]);
+        set_note(&mut song, 0, 0, 60);
+        set_effect(&mut song, 0, 0, EffectType::Extended, 0x14);
+        set_effect(&mut song, 0, 1, EffectType::Extended, 0x22);
+        let mut sequencer = play(song, 2);
+
+        let pitches: Vec<f32> = ticks(&mut sequencer, 4).iter().map(|tick| tick.pitch).collect();
+        assert_eq!(pitches, [0.25, 0.25, 0.125, 0.125]);
+    }
+
+    #[test]
+    fn fine_volume_slides_move_once_on_the_first_tick() {
+        let mut song = song(&[2], &[0]);
+        set_note(&mut song, 0, 0, 60);
+        song.patterns[0].rows[0].channels[0].volume = Some(32);
+        set_effect(&mut song, 0, 0, EffectType::Extended, 0xA4);
+        set_effect(&mut song, 0, 1, EffectType::Extended, 0xB8);
+        let mut sequencer = play(song, 2);
+
+        let gains: Vec<f32> = ticks(&mut sequencer, 4).iter().map(|tick| tick.gain).collect();
+        assert_eq!(gains, [36.0 / MAX_VOLUME, 36.0 / MAX_VOLUME, 28.0 / MAX_VOLUME, 28.0 / MAX_VOLUME]);
+    }
+
+    #[test]
+    fn sample_offset_starts_the_note_later_and_remembers_its_value() {
+        let mut song = song(&[3], &[0]);
+        for (row, note) in [60, 62, 64].into_iter().enumerate() {
+            set_note(&mut song, 0, row, note);
+        }
+        set_effect(&mut song, 0, 0, EffectType::SampleOffset, 0x02);
+        set_effect(&mut song, 0, 1, EffectType::SampleOffset, 0x00);
+        let mut sequencer = play(song, 1);
+
+        let notes: Vec<_> = ticks(&mut sequencer, 3).iter().map(|tick| tick.note_on).collect();
+        assert_eq!(notes, [Some((60, 512)), Some((62, 512)), Some((64, 0))]);
+    }
+
+    #[test]
+    fn retrigger_restarts_the_note_every_few_ticks() {
+        let mut song = song(&[1], &[0]);
+        set_note(&mut song, 0, 0, 60);
+        set_effect(&mut song, 0, 0, EffectType::Extended, 0x93);
+        let mut sequencer = play(song, 6);
+
+        let retriggered: Vec<bool> = ticks(&mut sequencer, 6).iter().map(|tick| tick.note_on == Some((60, 0))).collect();
+        assert_eq!(retriggered, [true, false, false, true, false, false]);
+    }
+
+    #[test]
+    fn note_cut_silences_the_note_on_its_tick() {
+        let mut song = song(&[1], &[0]);
+        set_note(&mut song, 0, 0, 60);
+        set_effect(&mut song, 0, 0, EffectType::Extended, 0xC2);
+        let mut sequencer = play(song, 4);
+
+        let gains: Vec<f32> = ticks(&mut sequencer, 4).iter().map(|tick| tick.gain).collect();
+        assert_eq!(gains, [1.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn note_delay_holds_the_note_until_its_tick() {
+        let mut song = song(&[1], &[0]);
+        set_note(&mut song, 0, 0, 60);
+        set_effect(&mut song, 0, 0, EffectType::Extended, 0xD2);
+        let mut sequencer = play(song, 4);
+
+        let notes: Vec<_> = ticks(&mut sequencer, 4).iter().map(|tick| tick.note_on).collect();
+        assert_eq!(notes, [None, None, Some((60, 0)), None]);
+    }
+
+    #[test]
+    fn song_mode_wraps_to_the_start_of_the_order_list() {
+        let mut sequencer = sequencer(song(&[2, 1], &[0, 1, 0]), PlayMode::Song);
+        sequencer.play(0, 0, 0);
+        assert_eq!(
+            positions(&mut sequencer, 6),
+            [(0, 0, 0), (0, 0, 1), (1, 1, 0), (2, 0, 0), (2, 0, 1), (0, 0, 0)],
+        );
+    }
+
+    #[test]
+    fn pattern_mode_loops_the_pattern_it_started_on() {
+        let mut sequencer = sequencer(song(&[2, 3], &[0, 1]), PlayMode::Pattern);
+        sequencer.play(0, 1, 0);
+        assert_eq!(
+            positions(&mut sequencer, 5),
+            [(0, 1, 0), (0, 1, 1), (0, 1, 2), (0, 1, 0), (0, 1, 1)],
+        );
+    }
+
+    #[test]
+    fn position_jump_on_the_last_row_goes_to_that_position() {
+        let mut song = song(&[2, 2, 2], &[0, 1, 2]);
+        set_effect(&mut song, 1, 1, EffectType::PositionJump, 0x00);
+        let mut sequencer = sequencer(song, PlayMode::Song);
+        sequencer.play(1, 0, 0);
+        assert_eq!(positions(&mut sequencer, 3), [(1, 1, 0), (1, 1, 1), (0, 0, 0)]);
+    }
+
+    #[test]
+    fn pattern_break_on_the_last_row_starts_the_next_position_at_its_row() {
+        let mut song = song(&[2, 3], &[0, 1]);
+        set_effect(&mut song, 0, 1, EffectType::PatternBreak, 0x02);
+        let mut sequencer = sequencer(song, PlayMode::Song);
+        sequencer.play(0, 0, 0);
+        assert_eq!(positions(&mut sequencer, 4), [(0, 0, 0), (0, 0, 1), (1, 1, 2), (0, 0, 0)]);
+    }
+
+    #[test]
+    fn pattern_loop_repeats_the_section_count_times() {
+        let mut song = song(&[3], &[0]);
+        set_effect(&mut song, 0, 0, EffectType::Extended, 0x60);
+        set_effect(&mut song, 0, 1, EffectType::Extended, 0x62);
+        let mut sequencer = sequencer(song, PlayMode::Song);
+        sequencer.play(0, 0, 0);
+        let rows: Vec<usize> = positions(&mut sequencer, 7).into_iter().map(|(_, _, row)| row).collect();
+        assert_eq!(rows, [0, 1, 0, 1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn set_speed_reports_the_new_tempo() {
+        let mut song = song(&[2], &[0]);
+        set_effect(&mut song, 0, 0, EffectType::SetSpeed, 0x03);
+        set_effect(&mut song, 0, 1, EffectType::SetSpeed, 0x20);
+        let mut sequencer = sequencer(song, PlayMode::Pattern);
+        sequencer.play(0, 0, 0);
+
+        let mut tempos = Vec::new();
+        for _ in 0..4 {
+            sequencer.advance(SAMPLE_RATE, |event| {
+                if let SequencerEvent::Tempo { bpm, speed } = event {
+                    tempos.push((bpm, speed));
+                }
+            });
+        }
+        assert_eq!(tempos, [(BPM, 3), (32.0, 3)]);
+    }
+
+    #[test]
+    fn shrinking_the_pattern_under_the_play_head_moves_on() {
+        let mut sequencer = sequencer(song(&[4, 2], &[0, 1]), PlayMode::Song);
+        sequencer.play(0, 0, 0);
+        assert_eq!(positions(&mut sequencer, 3), [(0, 0, 0), (0, 0, 1), (0, 0, 2)]);
+
+        // Row 3 is next, but the pattern is now only two rows long
+        sequencer.load_song(song(&[2, 2], &[0, 1]), 0, 0);
+        assert_eq!(positions(&mut sequencer, 2), [(1, 1, 0), (1, 1, 1)]);
+    }
+
+    #[test]
+    fn shrinking_a_looped_pattern_wraps_to_its_start() {
+        let mut sequencer = sequencer(song(&[4], &[0]), PlayMode::Pattern);
+        sequencer.play(0, 0, 0);
+        positions(&mut sequencer, 3);
+
+        sequencer.load_song(song(&[2], &[0]), 0, 0);
+        assert_eq!(positions(&mut sequencer, 2), [(0, 0, 0), (0, 0, 1)]);
+    }
+}
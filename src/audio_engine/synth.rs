@@ -0,0 +1,509 @@
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+use std::sync::Arc;
+
+use crate::data::{self, AuxBusSettings, FilterSettings, InstrumentSettings, MasterSettings, ModDestination, SourceType, VoiceSettings, VoiceStealing};
+use super::bus::AuxUnit;
+use super::distortion::Waveshaper;
+use super::drum::DrumVoice;
+use super::dynamics::MasterBus;
+use super::envelope::Adsr;
+use super::filter::Svf;
+use super::fm::FmVoice;
+use super::modulation::{LfoClock, Modulation};
+use super::oscillator::Oscillator;
+use super::sampler::{SampleData, SamplePlayer};
+use super::wavetable::{WavetableData, WavetableOscillator};
+
+/// Size of the shared voice pool. Voices are handed to whichever track needs one,
+/// so release tails from one track can overlap new notes on another.
+const MAX_VOICES: usize = 32;
+
+struct Voice {
+    track: usize,
+    instrument: usize,
+    note: u8,
+    /// Trigger order, for oldest-first stealing
+    age: u64,
+    osc: Oscillator,
+    player: SamplePlayer,
+    fm: FmVoice,
+    wavetable: WavetableOscillator,
+    drum: DrumVoice,
+    modulation: Modulation,
+    velocity: f32,
+    envelope: Adsr,
+    svf: Svf,
+    shaper: Waveshaper,
+    /// Slide, arpeggio and vibrato offset in semitones, and the volume column gain. Pattern
+    /// effects only move the voice their track triggered last, so earlier tails keep theirs.
+    pattern_pitch: f32,
+    pattern_gain: f32,
+}
+
+impl Voice {
+    /// Starts `note`; the caller has already assigned the voice its track and instrument.
+    fn trigger(&mut self, note: u8, velocity: u8, settings: &InstrumentSettings, filter: &FilterSettings, clock: &LfoClock, sample_rate: f32) {
+        // A stolen voice keeps its phase; the envelope ramps from its current level, so neither clicks
+        if !self.envelope.is_active() {
+            self.osc.reset();
+            self.wavetable.reset();
+        }
+        match settings.source {
+            SourceType::Fm => self.fm.note_on(&settings.fm, sample_rate),
+            SourceType::Drum => self.drum.note_on(&settings.drum, sample_rate),
+            _ => {},
+        }
+        self.note = note;
+        self.velocity = (velocity as f32 / 127.0) * 0.5;
+        self.envelope.note_on(&settings.envelope, sample_rate);
+        self.modulation.note_on(&settings.modulation, note, velocity, clock, sample_rate);
+        self.svf.set(filter, sample_rate);
+        self.shaper.set(settings.drive);
+    }
+
+    fn release(&mut self) {
+        self.envelope.note_off();
+        self.fm.note_off();
+        self.modulation.note_off();
+    }
+
+    fn loudness(&self) -> f32 {
+        self.envelope.level() * self.velocity
+    }
+}
+
+impl Default for Voice {
+    fn default() -> Self {
+        Self {
+            track: 0,
+            instrument: 0,
+            note: 0,
+            age: 0,
+            osc: Oscillator::default(),
+            player: SamplePlayer::default(),
+            fm: FmVoice::default(),
+            wavetable: WavetableOscillator::default(),
+            drum: DrumVoice::default(),
+            modulation: Modulation::default(),
+            velocity: 0.0,
+            envelope: Adsr::default(),
+            svf: Svf::default(),
+            shaper: Waveshaper::default(),
+            pattern_pitch: 0.0,
+            pattern_gain: 1.0,
+        }
+    }
+}
+
+/// An instrument definition as last sent by the UI, plus its decoded sample and wavetables.
+#[derive(Default)]
+struct InstrumentState {
+    settings: InstrumentSettings,
+    sample: Option<Arc<SampleData>>,
+    wavetable: Option<Arc<WavetableData>>,
+}
+
+/// Mixer settings the UI has sent for one pattern track.
+#[derive(Default)]
+struct TrackState {
+    voices: VoiceSettings,
+    sends: [f32; data::MAX_AUX_BUSES],
+    pan: f32,
+    // Pattern filter and pan commands override the instrument's cutoff/resonance and the
+    // mixer's pan on this track until stop
+    cutoff: Option<f32>,
+    resonance: Option<f32>,
+    pattern_pan: Option<f32>,
+    /// The channel's slide and arpeggio offset in semitones, which new notes start from
+    pattern_pitch: f32,
+    /// The channel's volume column, volume slide and tremolo gain, which new notes start from
+    pattern_gain: Option<f32>,
+    /// The voice the track last triggered and its age, so a stolen voice isn't mistaken for it
+    last_voice: Option<(usize, u64)>,
+}
+
+impl TrackState {
+    fn filter(&self, instrument: &InstrumentSettings) -> FilterSettings {
+        FilterSettings {
+            cutoff: self.cutoff.unwrap_or(instrument.filter.cutoff),
+            resonance: self.resonance.unwrap_or(instrument.filter.resonance),
+            ..instrument.filter
+        }
+    }
+
+    fn pan(&self) -> f32 {
+        self.pattern_pan.unwrap_or(self.pan)
+    }
+
+    fn gain(&self) -> f32 {
+        self.pattern_gain.unwrap_or(1.0)
+    }
+}
+
+/// The voices, instrument bank, per-track settings, aux buses and master bus. Lives entirely on the audio thread.
+pub struct Synth {
+    sample_rate: f32,
+    voices: [Voice; MAX_VOICES],
+    next_age: u64,
+    instruments: [InstrumentState; data::MAX_INSTRUMENTS],
+    tracks: [TrackState; data::MAX_TRACKS],
+    aux: [AuxUnit; data::MAX_AUX_BUSES],
+    master: MasterBus,
+    clock: LfoClock,
+}
+
+impl Synth {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            voices: Default::default(),
+            next_age: 0,
+            instruments: std::array::from_fn(|_| InstrumentState::default()),
+            tracks: Default::default(),
+            aux: std::array::from_fn(|_| AuxUnit::new(sample_rate)),
+            master: MasterBus::new(sample_rate),
+            clock: LfoClock::default(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Replaces an instrument definition. Voices already playing it pick up the change.
+    pub fn set_instrument(&mut self, index: usize, settings: InstrumentSettings) {
+        let Some(instrument) = self.instruments.get_mut(index) else {
+            return;
+        };
+        instrument.settings = settings;
+        for voice in self.voices.iter_mut().filter(|v| v.instrument == index) {
+            voice.svf.set(&self.tracks[voice.track].filter(&settings), self.sample_rate);
+            voice.shaper.set(settings.drive);
+        }
+    }
+
+    /// Swaps an instrument's sample, handing back the one it replaced (or `sample` itself
+    /// if the slot doesn't exist) so the caller can free it off the audio thread.
+    pub fn set_sample(&mut self, index: usize, sample: Option<Arc<SampleData>>) -> Option<Arc<SampleData>> {
+        match self.instruments.get_mut(index) {
+            Some(instrument) => std::mem::replace(&mut instrument.sample, sample),
+            None => sample,
+        }
+    }
+
+    /// Swaps an instrument's wavetables, handing back the old set like `set_sample`.
+    pub fn set_wavetable(&mut self, index: usize, wavetable: Option<Arc<WavetableData>>) -> Option<Arc<WavetableData>> {
+        match self.instruments.get_mut(index) {
+            Some(instrument) => std::mem::replace(&mut instrument.wavetable, wavetable),
+            None => wavetable,
+        }
+    }
+
+    pub fn set_voices(&mut self, track: usize, voices: VoiceSettings) {
+        if let Some(state) = self.tracks.get_mut(track) {
+            state.voices = voices;
+        }
+    }
+
+    /// Pattern effects move the cutoff without touching the mode or resonance.
+    pub fn set_filter_cutoff(&mut self, track: usize, cutoff: f32) {
+        if let Some(state) = self.tracks.get_mut(track) {
+            state.cutoff = Some(cutoff);
+            self.refresh_filters(track);
+        }
+    }
+
+    pub fn set_filter_resonance(&mut self, track: usize, resonance: f32) {
+        if let Some(state) = self.tracks.get_mut(track) {
+            state.resonance = Some(resonance);
+            self.refresh_filters(track);
+        }
+    }
+
+    fn refresh_filters(&mut self, track: usize) {
+        let state = &self.tracks[track];
+        for voice in self.voices.iter_mut().filter(|v| v.track == track) {
+            let filter = state.filter(&self.instruments[voice.instrument].settings);
+            voice.svf.set(&filter, self.sample_rate);
+        }
+    }
+
+    pub fn set_send(&mut self, track: usize, bus: usize, amount: f32) {
+        if let Some(send) = self.tracks.get_mut(track).and_then(|state| state.sends.get_mut(bus)) {
+            *send = amount.clamp(0.0, 1.0);
+        }
+    }
+
+    pub fn set_aux_bus(&mut self, bus: usize, settings: AuxBusSettings) {
+        if let Some(unit) = self.aux.get_mut(bus) {
+            unit.set(settings);
+        }
+    }
+
+    pub fn set_pan(&mut self, track: usize, pan: f32) {
+        if let Some(state) = self.tracks.get_mut(track) {
+            state.pan = pan.clamp(-1.0, 1.0);
+        }
+    }
+
+    /// 8xx: moves the track, including notes already sounding, until stop.
+    pub fn set_pattern_pan(&mut self, track: usize, pan: f32) {
+        if let Some(state) = self.tracks.get_mut(track) {
+            state.pattern_pan = Some(pan.clamp(-1.0, 1.0));
+        }
+    }
+
+    /// Pitch slides and arpeggio: bends the track's current note, not the tails before it.
+    pub fn set_pattern_pitch(&mut self, track: usize, semitones: f32) {
+        if let Some(state) = self.tracks.get_mut(track) {
+            state.pattern_pitch = semitones;
+        }
+        if let Some(voice) = self.current_voice(track) {
+            voice.pattern_pitch = semitones;
+        }
+    }
+
+    /// Volume column and volume effects: scales the track's current note.
+    pub fn set_pattern_gain(&mut self, track: usize, gain: f32) {
+        let gain = gain.max(0.0);
+        if let Some(state) = self.tracks.get_mut(track) {
+            state.pattern_gain = Some(gain);
+        }
+        if let Some(voice) = self.current_voice(track) {
+            voice.pattern_gain = gain;
+        }
+    }
+
+    /// The voice the track triggered last, if it is still playing for that track.
+    fn current_voice(&mut self, track: usize) -> Option<&mut Voice> {
+        let (index, age) = self.tracks.get(track)?.last_voice?;
+        self.voices.get_mut(index).filter(|voice| voice.age == age && voice.track == track)
+    }
+
+    pub fn set_master(&mut self, master: MasterSettings) {
+        self.master.set(master);
+    }
+
+    /// Deepest master bus gain reduction since the last call, in dB.
+    pub fn take_gain_reduction(&mut self) -> f32 {
+        self.master.take_gain_reduction()
+    }
+
+    /// Tempo-synced effects and LFOs follow the sequencer's BPM.
+    pub fn set_tempo(&mut self, bpm: f32) {
+        for unit in self.aux.iter_mut() {
+            unit.set_tempo(bpm);
+        }
+        self.clock.set_tempo(bpm);
+    }
+
+    /// Starts `note` on `track`; sampler instruments start `sample_offset` frames into the sample.
+    pub fn note_on(&mut self, track: usize, note: u8, instrument: u8, velocity: u8, sample_offset: usize) {
+        let instrument = instrument as usize;
+        let (Some(state), Some(inst)) = (self.tracks.get(track), self.instruments.get(instrument)) else {
+            return;
+        };
+        let VoiceSettings { polyphony, stealing } = state.voices;
+        let settings = inst.settings;
+        let filter = state.filter(&settings);
+        let (pattern_pitch, pattern_gain) = (state.pattern_pitch, state.gain());
+
+        // The same note held again retriggers in place rather than stacking
+        let index = match self.voices.iter().position(|v| v.envelope.is_held() && v.track == track && v.note == note) {
+            Some(index) => index,
+            None => {
+                // Over the track's polyphony: release one of its held notes into its tail
+                let held = self.voices.iter().filter(|v| v.envelope.is_held() && v.track == track).count();
+                if held >= polyphony.max(1) as usize {
+                    if let Some(victim) = pick_victim(&self.voices, stealing, |v| v.envelope.is_held() && v.track == track) {
+                        self.voices[victim].release();
+                    }
+                }
+                self.allocate(stealing)
+            },
+        };
+
+        self.next_age += 1;
+        let voice = &mut self.voices[index];
+        voice.age = self.next_age;
+        voice.track = track;
+        voice.instrument = instrument;
+        voice.trigger(note, velocity, &settings, &filter, &self.clock, self.sample_rate);
+        voice.player.start(sample_offset);
+        voice.pattern_pitch = pattern_pitch;
+        voice.pattern_gain = pattern_gain;
+        self.tracks[track].last_voice = Some((index, self.next_age));
+    }
+
+    /// A free voice if there is one; otherwise the quietest release tail, and only then a held note.
+    fn allocate(&self, stealing: VoiceStealing) -> usize {
+        self.voices.iter().position(|v| !v.envelope.is_active())
+            .or_else(|| pick_victim(&self.voices, VoiceStealing::Quietest, |v| !v.envelope.is_held()))
+            .or_else(|| pick_victim(&self.voices, stealing, |_| true))
+            .unwrap_or(0)
+    }
+
+    /// Releases `note` on `track`, or every held note on the track when `note` is `None`.
+    /// One-shot samples and drums ignore this and play to their end.
+    pub fn note_off(&mut self, track: usize, note: Option<u8>) {
+        for voice in self.voices.iter_mut() {
+            if voice.track != track || note.is_some_and(|n| n != voice.note) {
+                continue;
+            }
+            let settings = &self.instruments[voice.instrument].settings;
+            let one_shot = match settings.source {
+                SourceType::Sampler => settings.sampler.one_shot,
+                SourceType::Drum => true,
+                _ => false,
+            };
+            if one_shot {
+                continue;
+            }
+            voice.release();
+        }
+    }
+
+    /// Stop: everything releases, one-shots included, and pattern filter, pan, pitch and volume overrides reset.
+    pub fn release_all(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.release();
+        }
+        for track in 0..self.tracks.len() {
+            self.tracks[track].cutoff = None;
+            self.tracks[track].resonance = None;
+            self.tracks[track].pattern_pan = None;
+            self.tracks[track].pattern_pitch = 0.0;
+            self.tracks[track].pattern_gain = None;
+            self.tracks[track].last_voice = None;
+            self.refresh_filters(track);
+        }
+    }
+
+    pub fn render(&mut self) -> [f32; 2] {
+        let mut track_outputs = [[0.0_f32; 2]; data::MAX_TRACKS];
+        for voice in self.voices.iter_mut() {
+            if !voice.envelope.is_active() {
+                continue;
+            }
+            let instrument = &self.instruments[voice.instrument];
+            let settings = &instrument.settings;
+            let mods = voice.modulation.next(&settings.modulation, voice.envelope.level(), &self.clock, self.sample_rate);
+            if settings.modulation.routes_to(ModDestination::Cutoff) {
+                let mut filter = self.tracks[voice.track].filter(settings);
+                filter.cutoff = (filter.cutoff + mods.cutoff).clamp(0.0, 1.0);
+                voice.svf.set(&filter, self.sample_rate);
+            }
+
+            let track = &self.tracks[voice.track];
+            let note = voice.note as f32 + voice.pattern_pitch;
+            let freq = 440.0 * 2.0_f32.powf((note - 69.0 + mods.pitch) / 12.0);
+            let value = match settings.source {
+                SourceType::Oscillator => {
+                    let pulse_width = (settings.oscillator.pulse_width + mods.pulse_width).clamp(0.05, 0.95);
+                    voice.osc.next(settings.oscillator.waveform, freq, pulse_width, self.sample_rate)
+                },
+                SourceType::Fm => voice.fm.next(&settings.fm, freq, self.sample_rate),
+                SourceType::Wavetable => match &instrument.wavetable {
+                    Some(wavetable) => voice.wavetable.next(wavetable, settings.wavetable.position, freq, self.sample_rate),
+                    None => 0.0,
+                },
+                SourceType::Drum => match voice.drum.next(&settings.drum, note, self.sample_rate) {
+                    Some(value) => value,
+                    None => {
+                        voice.envelope.kill();
+                        continue;
+                    },
+                },
+                SourceType::Sampler => {
+                    let next = instrument.sample.as_ref()
+                        .and_then(|sample| voice.player.next(sample, &settings.sampler, note, self.sample_rate));
+                    match next {
+                        Some(value) => value,
+                        None => {
+                            // Played out (or nothing loaded): free the voice
+                            voice.envelope.kill();
+                            continue;
+                        },
+                    }
+                },
+            };
+
+            let gain = voice.velocity * voice.envelope.next() * (1.0 + mods.volume).max(0.0) * voice.pattern_gain;
+            let out = voice.svf.process(voice.shaper.process(value * gain));
+            let [left, right] = pan_gains(track.pan() + mods.pan);
+            track_outputs[voice.track][0] += out * left;
+            track_outputs[voice.track][1] += out * right;
+        }
+        self.clock.advance(self.sample_rate);
+
+        let mut mixed = [0.0_f32; 2];
+        let mut aux_in = [0.0_f32; data::MAX_AUX_BUSES];
+        for (track, [left, right]) in self.tracks.iter().zip(track_outputs) {
+            mixed[0] += left;
+            mixed[1] += right;
+            let mono = (left + right) * 0.5;
+            for (input, send) in aux_in.iter_mut().zip(track.sends) {
+                *input += mono * send;
+            }
+        }
+
+        for (unit, input) in self.aux.iter_mut().zip(aux_in) {
+            let [left, right] = unit.process(input);
+            mixed[0] += left;
+            mixed[1] += right;
+        }
+        self.master.process(mixed)
+    }
+}
+
+/// Equal-power pan law, scaled so the centre stays at unity gain.
+fn pan_gains(pan: f32) -> [f32; 2] {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+    [angle.cos() * SQRT_2, angle.sin() * SQRT_2]
+}
+
+fn pick_victim(voices: &[Voice], stealing: VoiceStealing, candidate: impl Fn(&Voice) -> bool) -> Option<usize> {
+    let candidates = voices.iter().enumerate().filter(|(_, v)| candidate(v));
+    match stealing {
+        VoiceStealing::Oldest => candidates.min_by_key(|(_, v)| v.age),
+        VoiceStealing::Quietest => candidates.min_by(|(_, a), (_, b)| a.loudness().total_cmp(&b.loudness())),
+    }
+    .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(synth: &Synth, note: u8) -> &Voice {
+        synth.voices.iter().find(|v| v.envelope.is_active() && v.note == note).unwrap()
+    }
+
+    #[test]
+    fn pattern_effects_leave_earlier_tails_alone() {
+        let mut synth = Synth::new(48000.0);
+        synth.note_on(0, 60, 0, 127, 0);
+        synth.set_pattern_pitch(0, 1.0);
+        synth.set_pattern_gain(0, 0.5);
+        synth.note_on(0, 64, 0, 127, 0);
+        synth.set_pattern_pitch(0, 2.0);
+        synth.set_pattern_gain(0, 0.25);
+
+        let tail = voice(&synth, 60);
+        assert!(!tail.envelope.is_held());
+        assert_eq!((tail.pattern_pitch, tail.pattern_gain), (1.0, 0.5));
+        let current = voice(&synth, 64);
+        assert_eq!((current.pattern_pitch, current.pattern_gain), (2.0, 0.25));
+    }
+
+    #[test]
+    fn new_notes_start_from_the_track_pitch_and_volume() {
+        let mut synth = Synth::new(48000.0);
+        synth.set_pattern_pitch(0, 3.0);
+        synth.set_pattern_gain(0, 0.5);
+        synth.note_on(0, 60, 0, 127, 0);
+
+        let voice = voice(&synth, 60);
+        assert_eq!((voice.pattern_pitch, voice.pattern_gain), (3.0, 0.5));
+    }
+}
@@ -0,0 +1,198 @@
+use std::f32::consts::TAU;
+use std::path::Path;
+
+use crate::data::{MAX_WAVETABLES, WAVETABLE_SIZE};
+use super::sampler::{SampleData, SampleError};
+
+/// One band-limited copy per octave: level `n` keeps the first `WAVETABLE_SIZE / 2 >> n` harmonics.
+const LEVELS: usize = 8;
+
+/// A set of single-cycle tables, each pre-filtered into per-octave mip levels.
+/// Built on the UI side and shared with the audio thread behind an `Arc`.
+pub struct WavetableData {
+    /// `tables[table][level]`, `WAVETABLE_SIZE` points each
+    tables: Vec<Vec<Vec<f32>>>,
+}
+
+impl WavetableData {
+    /// Band-limits each table by resynthesising it from its harmonics, one octave's worth
+    /// fewer per level. Tables of the wrong length are resampled first, and only the first
+    /// `MAX_WAVETABLES` are used. `None` if there are no tables.
+    pub fn build(tables: &[Vec<f32>]) -> Option<Self> {
+        if tables.is_empty() {
+            return None;
+        }
+        let tables = tables.iter().take(MAX_WAVETABLES).map(|table| {
+            let table = resample(table, WAVETABLE_SIZE);
+            let harmonics = analyse(&table);
+            (0..LEVELS).map(|level| synthesise(&harmonics[..harmonics.len() >> level])).collect()
+        })
+        .collect();
+        Some(Self { tables })
+    }
+
+    /// Reads `table` at `phase` (0-1) from the mip level whose harmonics all stay below Nyquist.
+    fn read(&self, table: usize, level: usize, phase: f32) -> f32 {
+        let points = &self.tables[table][level];
+        let position = phase * WAVETABLE_SIZE as f32;
+        let index = position as usize % WAVETABLE_SIZE;
+        let frac = position.fract();
+        let next = (index + 1) % WAVETABLE_SIZE;
+        points[index] + (points[next] - points[index]) * frac
+    }
+}
+
+/// Imports a WAV or FLAC file as one cycle: the whole file is stretched to `WAVETABLE_SIZE` points.
+pub fn load_cycle(path: impl AsRef<Path>) -> Result<Vec<f32>, SampleError> {
+    let sample = SampleData::load(path)?;
+    Ok(resample(sample.frames(), WAVETABLE_SIZE))
+}
+
+/// Linear resampling of one cycle to `len` points.
+fn resample(points: &[f32], len: usize) -> Vec<f32> {
+    if points.is_empty() {
+        return vec![0.0; len];
+    }
+    (0..len).map(|i| {
+        let position = i as f32 * points.len() as f32 / len as f32;
+        let index = position as usize;
+        let next = (index + 1) % points.len();
+        points[index] + (points[next] - points[index]) * position.fract()
+    })
+    .collect()
+}
+
+/// Cosine/sine amplitudes of harmonics 1..N/2 (DC is dropped).
+fn analyse(points: &[f32]) -> Vec<(f32, f32)> {
+    let n = points.len();
+    (1..=n / 2).map(|k| {
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, &x) in points.iter().enumerate() {
+            let angle = TAU * (k * i % n) as f32 / n as f32;
+            re += x * angle.cos();
+            im += x * angle.sin();
+        }
+        let scale = if k == n / 2 { 1.0 } else { 2.0 } / n as f32;
+        (re * scale, im * scale)
+    })
+    .collect()
+}
+
+fn synthesise(harmonics: &[(f32, f32)]) -> Vec<f32> {
+    (0..WAVETABLE_SIZE).map(|i| {
+        harmonics.iter().enumerate().map(|(h, &(re, im))| {
+            let angle = TAU * ((h + 1) * i % WAVETABLE_SIZE) as f32 / WAVETABLE_SIZE as f32;
+            re * angle.cos() + im * angle.sin()
+        })
+        .sum()
+    })
+    .collect()
+}
+
+/// Per-voice phase for the wavetable source.
+#[derive(Default)]
+pub struct WavetableOscillator {
+    phase: f32,
+}
+
+impl WavetableOscillator {
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    /// `position` (0-1) crossfades through the tables from first to last.
+    pub fn next(&mut self, data: &WavetableData, position: f32, freq: f32, sample_rate: f32) -> f32 {
+        let dt = (freq / sample_rate).min(0.5);
+
+        // Pick the first level whose top harmonic sits below Nyquist
+        let top_harmonic = WAVETABLE_SIZE / 2;
+        let level = (0..LEVELS).find(|&level| (top_harmonic >> level) as f32 * dt < 0.5).unwrap_or(LEVELS - 1);
+
+        let last = data.tables.len() - 1;
+        let scaled = position.clamp(0.0, 1.0) * last as f32;
+        let table = (scaled as usize).min(last);
+        let frac = scaled - table as f32;
+        let a = data.read(table, level, self.phase);
+        let value = if frac > 0.0 { a + (data.read(table + 1, level, self.phase) - a) * frac } else { a };
+
+        self.phase += dt;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn sine(amplitude: f32) -> Vec<f32> {
+        (0..WAVETABLE_SIZE).map(|i| amplitude * (i as f32 / WAVETABLE_SIZE as f32 * TAU).sin()).collect()
+    }
+
+    fn saw() -> Vec<f32> {
+        (0..WAVETABLE_SIZE).map(|i| 2.0 * i as f32 / WAVETABLE_SIZE as f32 - 1.0).collect()
+    }
+
+    #[test]
+    fn build_keeps_a_sine_and_drops_dc() {
+        let offset: Vec<f32> = sine(1.0).iter().map(|v| v + 0.5).collect();
+        let built = WavetableData::build(&[offset]).unwrap();
+        for (a, b) in built.tables[0][0].iter().zip(sine(1.0)) {
+            assert!((a - b).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn each_mip_level_drops_an_octave_of_harmonics() {
+        let built = WavetableData::build(&[saw()]).unwrap();
+        for (level, points) in built.tables[0].iter().enumerate() {
+            let kept = (WAVETABLE_SIZE / 2) >> level;
+            let harmonics = analyse(points);
+            let (re, im) = harmonics[kept - 1];
+            assert!(re.hypot(im) > 1e-3, "level {} lost harmonic {}", level, kept);
+            assert!(harmonics[kept..].iter().all(|(re, im)| re.hypot(*im) < 1e-3), "level {} kept harmonics past {}", level, kept);
+        }
+    }
+
+    #[test]
+    fn oscillator_plays_the_table_at_the_note_and_crossfades_by_position() {
+        let built = WavetableData::build(&[sine(1.0), sine(-1.0)]).unwrap();
+        let render = |position: f32| {
+            let mut osc = WavetableOscillator::default();
+            (0..1000).map(|_| osc.next(&built, position, 480.0, SAMPLE_RATE)).collect::<Vec<f32>>()
+        };
+        let first = render(0.0);
+        for (i, v) in first.iter().enumerate() {
+            assert!((v - (i as f32 * 480.0 / SAMPLE_RATE * TAU).sin()).abs() < 1e-3);
+        }
+        assert!(render(0.5).iter().all(|v| v.abs() < 1e-3));
+        assert!(render(1.0).iter().zip(&first).all(|(a, b)| (a + b).abs() < 1e-3));
+    }
+
+    #[test]
+    fn high_notes_read_a_level_without_harmonics_past_nyquist() {
+        let built = WavetableData::build(&[saw()]).unwrap();
+        let mut osc = WavetableOscillator::default();
+        // At 5 kHz only the first four harmonics fit under 24 kHz, which is level 5
+        let output: Vec<f32> = (0..WAVETABLE_SIZE).map(|_| osc.next(&built, 0.0, 5000.0, SAMPLE_RATE)).collect();
+        let mut phase = 0.0;
+        for v in output {
+            assert_eq!(v, built.read(0, 5, phase));
+            phase += 5000.0 / SAMPLE_RATE;
+            if phase >= 1.0 {
+                phase -= 1.0;
+            }
+        }
+    }
+
+    #[test]
+    fn build_keeps_at_most_max_wavetables() {
+        let tables = vec![vec![0.0; WAVETABLE_SIZE]; MAX_WAVETABLES + 2];
+        let built = WavetableData::build(&tables).unwrap();
+        assert_eq!(built.tables.len(), MAX_WAVETABLES);
+    }
+}
@@ -1,14 +1,30 @@
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
-pub const NUM_CHANNELS: usize = 16;
+/// Tracks in a new project
+pub const DEFAULT_TRACKS: usize = 16;
+/// Upper bound on the track count, which the audio engine preallocates.
+pub const MAX_TRACKS: usize = 32;
+/// Length of a new pattern; each pattern can be resized from 1 to `MAX_PATTERN_ROWS`
 pub const ROWS_PER_PATTERN: usize = 64;
+pub const MAX_PATTERN_ROWS: usize = 256;
+/// Note value that releases whatever is sounding on the channel (shown as "OFF")
+pub const NOTE_OFF: u8 = 0xFF;
 
 #[derive(Resource, Serialize, Deserialize, Clone)]
 pub struct ProjectData {
     pub bpm: u32,
-    pub patterns: Vec<Pattern>,
+    /// Private, like `order`, so every edit goes through `song_mut` and playback sees it
+    patterns: Vec<Pattern>,
     pub current_pattern: usize,
+    /// The arrangement: pattern indices in play order, repeats allowed
+    #[serde(default = "default_order")]
+    order: Vec<usize>,
+    /// Position in `order` the song is at
+    #[serde(default)]
+    pub current_order: usize,
+    #[serde(default)]
+    pub play_mode: PlayMode,
     pub playing: bool,
     pub current_row: usize,
     pub current_channel: usize,
@@ -20,7 +36,28 @@ pub struct ProjectData {
     pub auto_advance: usize, 
     
     // Pro Features (Metadata)
+    /// One per column; every pattern row has exactly this many cells, in the same order
     pub tracks: Vec<TrackConfig>,
+    #[serde(default = "default_instruments")]
+    pub instruments: Vec<Instrument>,
+    #[serde(default = "default_aux_buses")]
+    pub aux_buses: Vec<AuxBus>,
+    #[serde(default)]
+    pub master: MasterSettings,
+    /// Bumped whenever tracks are removed or reordered, so the engine can drop state it
+    /// holds per track position
+    #[serde(skip)]
+    pub track_layout: u64,
+    /// Bumped on every edit to the patterns or the order list, so playback only rebuilds
+    /// the engine's copy of the song when there is something new in it
+    #[serde(skip)]
+    song_generation: u64,
+}
+
+/// Write access to the patterns and the order list, handed out by `ProjectData::song_mut`.
+pub struct SongMut<'a> {
+    pub patterns: &'a mut Vec<Pattern>,
+    pub order: &'a mut Vec<usize>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -30,6 +67,17 @@ pub struct TrackConfig {
     pub muted: bool,
     pub solo: bool,
     pub volume: f32,
+    /// Instrument entered with new notes, and played by notes with no instrument column
+    #[serde(default)]
+    pub instrument: u8,
+    #[serde(default)]
+    pub voices: VoiceSettings,
+    /// Amount of this track sent to each aux bus (0-1), by position in `ProjectData::aux_buses`
+    #[serde(default)]
+    pub sends: [f32; MAX_AUX_BUSES],
+    /// Stereo position, -1 (left) to 1 (right)
+    #[serde(default)]
+    pub pan: f32,
 }
 
 impl Default for TrackConfig {
@@ -40,6 +88,859 @@ impl Default for TrackConfig {
             muted: false,
             solo: false,
             volume: 1.0,
+            instrument: 0,
+            voices: VoiceSettings::default(),
+            sends: [0.0; MAX_AUX_BUSES],
+            pan: 0.0,
+        }
+    }
+}
+
+/// Upper bound on the instrument bank, which the audio engine preallocates.
+pub const MAX_INSTRUMENTS: usize = 64;
+
+/// Where an instrument's sound comes from.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourceType {
+    #[default]
+    Oscillator,
+    Sampler,
+    Fm,
+    Wavetable,
+    Drum,
+}
+
+impl SourceType {
+    pub const ALL: [SourceType; 5] = [SourceType::Oscillator, SourceType::Sampler, SourceType::Fm, SourceType::Wavetable, SourceType::Drum];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SourceType::Oscillator => "OSC",
+            SourceType::Sampler => "SAMPLE",
+            SourceType::Fm => "FM",
+            SourceType::Wavetable => "WAVE",
+            SourceType::Drum => "DRUM",
+        }
+    }
+}
+
+/// Everything the audio engine needs to play an instrument. Plain data, so a whole
+/// definition can be sent to the audio thread without allocating.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct InstrumentSettings {
+    #[serde(default)]
+    pub source: SourceType,
+    #[serde(default)]
+    pub oscillator: OscillatorSettings,
+    #[serde(default)]
+    pub sampler: SamplerSettings,
+    #[serde(default)]
+    pub fm: FmSettings,
+    #[serde(default)]
+    pub wavetable: WavetableSettings,
+    #[serde(default)]
+    pub drum: DrumSettings,
+    #[serde(default)]
+    pub envelope: EnvelopeSettings,
+    #[serde(default)]
+    pub filter: FilterSettings,
+    #[serde(default)]
+    pub drive: DriveSettings,
+    #[serde(default)]
+    pub modulation: ModulationSettings,
+}
+
+/// One entry in the project's instrument bank, indexed by the instrument column.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Instrument {
+    pub name: String,
+    /// WAV/FLAC file for the sampler source
+    #[serde(default)]
+    pub sample_path: Option<String>,
+    /// Single-cycle tables for the wavetable source, `WAVETABLE_SIZE` points each
+    #[serde(default)]
+    pub wavetables: Vec<Vec<f32>>,
+    #[serde(flatten)]
+    pub settings: InstrumentSettings,
+}
+
+impl Instrument {
+    pub fn new(name: &str, settings: InstrumentSettings) -> Self {
+        Self { name: name.to_string(), sample_path: None, wavetables: Vec::new(), settings }
+    }
+
+    pub fn oscillator(name: &str, waveform: Waveform) -> Self {
+        let mut settings = InstrumentSettings::default();
+        settings.oscillator.waveform = waveform;
+        Self::new(name, settings)
+    }
+
+    /// A one-shot drum sample, at its recorded pitch on C of the default octave.
+    pub fn drum_sample(name: &str, path: &str) -> Self {
+        let mut settings = InstrumentSettings { source: SourceType::Sampler, ..default() };
+        settings.sampler.root_note = 48;
+        settings.sampler.one_shot = true;
+        Self { sample_path: Some(path.to_string()), ..Self::new(name, settings) }
+    }
+
+    /// Two-operator bass: a fast-decaying modulator gives the pluck.
+    pub fn fm_bass(name: &str) -> Self {
+        let mut settings = InstrumentSettings { source: SourceType::Fm, ..default() };
+        settings.envelope = EnvelopeSettings { attack: 0.002, decay: 0.3, sustain: 0.7, release: 0.1 };
+        let modulator = &mut settings.fm.operators[1];
+        modulator.level = 0.6;
+        modulator.envelope = EnvelopeSettings { attack: 0.001, decay: 0.15, sustain: 0.2, release: 0.1 };
+        settings.fm.feedback = 0.2;
+        Self::new(name, settings)
+    }
+
+    /// Morphs from a sine into a saw across the position range.
+    pub fn wavetable(name: &str) -> Self {
+        let settings = InstrumentSettings { source: SourceType::Wavetable, ..default() };
+        Self { wavetables: default_wavetables(), ..Self::new(name, settings) }
+    }
+
+    /// A synthesized drum. The envelope stays open so the drum's own decay shapes it.
+    pub fn drum(name: &str, kind: DrumKind) -> Self {
+        let mut settings = InstrumentSettings { source: SourceType::Drum, drum: DrumSettings::new(kind), ..default() };
+        settings.envelope = EnvelopeSettings { attack: 0.0, decay: 0.0, sustain: 1.0, release: 0.05 };
+        Self::new(name, settings)
+    }
+}
+
+/// The bank new projects start with. The first four match the old fixed waveforms,
+/// so projects saved before the bank existed keep their sound.
+pub fn default_instruments() -> Vec<Instrument> {
+    vec![
+        Instrument::oscillator("SINE", Waveform::Sine),
+        Instrument::oscillator("SQUARE", Waveform::Square),
+        Instrument::oscillator("SAW", Waveform::Saw),
+        Instrument::oscillator("PULSE", Waveform::Pulse),
+        Instrument::drum_sample("KICK", "assets/samples/kick.wav"),
+        Instrument::drum_sample("SNARE", "assets/samples/snare.wav"),
+        Instrument::fm_bass("FM BASS"),
+        Instrument::wavetable("WAVE"),
+        Instrument::drum("808 KICK", DrumKind::Kick),
+        Instrument::drum("808 SNARE", DrumKind::Snare),
+        Instrument::drum("CL HAT", DrumKind::ClosedHat),
+        Instrument::drum("OP HAT", DrumKind::OpenHat),
+        Instrument::drum("CLAP", DrumKind::Clap),
+    ]
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Sine,
+    Square,
+    Saw,
+    Pulse,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 4] = [Waveform::Sine, Waveform::Square, Waveform::Saw, Waveform::Pulse];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Waveform::Sine => "SINE",
+            Waveform::Square => "SQUARE",
+            Waveform::Saw => "SAW",
+            Waveform::Pulse => "PULSE",
+        }
+    }
+}
+
+/// Pulse width is the high portion of each cycle (0-1); only the pulse waveform uses it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct OscillatorSettings {
+    #[serde(default)]
+    pub waveform: Waveform,
+    pub pulse_width: f32,
+}
+
+impl Default for OscillatorSettings {
+    fn default() -> Self {
+        Self { waveform: Waveform::Sine, pulse_width: 0.3 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoopMode {
+    #[default]
+    Off,
+    Forward,
+    PingPong,
+}
+
+impl LoopMode {
+    pub const ALL: [LoopMode; 3] = [LoopMode::Off, LoopMode::Forward, LoopMode::PingPong];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LoopMode::Off => "NO LOOP",
+            LoopMode::Forward => "LOOP",
+            LoopMode::PingPong => "PING-PONG",
+        }
+    }
+}
+
+/// How a track's sample is pitched and looped. Loop points are in sample frames;
+/// a loop end of 0 means the end of the sample. One-shot samples ignore note offs
+/// and loop points and always play through once.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SamplerSettings {
+    /// Note at which the sample plays at its recorded pitch
+    pub root_note: u8,
+    /// Cents, -100 to 100
+    pub fine_tune: f32,
+    pub loop_mode: LoopMode,
+    pub loop_start: u32,
+    pub loop_end: u32,
+    pub one_shot: bool,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            root_note: 60,
+            fine_tune: 0.0,
+            loop_mode: LoopMode::Off,
+            loop_start: 0,
+            loop_end: 0,
+            one_shot: false,
+        }
+    }
+}
+
+/// How the four FM operators feed each other. Operator 1 is always a carrier.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FmAlgorithm {
+    /// 2 -> 1, operators 3 and 4 unused
+    #[default]
+    TwoOp,
+    /// 4 -> 3 -> 2 -> 1
+    Stack,
+    /// (2 -> 1) + (4 -> 3)
+    TwoStacks,
+    /// (2 + 3 + 4) -> 1
+    ThreeToOne,
+    /// 4 -> (1 + 2 + 3)
+    OneToThree,
+    /// 1 + 2 + 3 + 4
+    Additive,
+}
+
+impl FmAlgorithm {
+    pub const ALL: [FmAlgorithm; 6] = [
+        FmAlgorithm::TwoOp, FmAlgorithm::Stack, FmAlgorithm::TwoStacks,
+        FmAlgorithm::ThreeToOne, FmAlgorithm::OneToThree, FmAlgorithm::Additive,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FmAlgorithm::TwoOp => "2>1",
+            FmAlgorithm::Stack => "4>3>2>1",
+            FmAlgorithm::TwoStacks => "2>1 + 4>3",
+            FmAlgorithm::ThreeToOne => "234>1",
+            FmAlgorithm::OneToThree => "4>123",
+            FmAlgorithm::Additive => "1+2+3+4",
+        }
+    }
+
+    /// Bitmask of the operators modulating each operator (bit n = operator n + 1).
+    pub fn modulators(self) -> [u8; 4] {
+        match self {
+            FmAlgorithm::TwoOp => [0b0010, 0, 0, 0],
+            FmAlgorithm::Stack => [0b0010, 0b0100, 0b1000, 0],
+            FmAlgorithm::TwoStacks => [0b0010, 0, 0b1000, 0],
+            FmAlgorithm::ThreeToOne => [0b1110, 0, 0, 0],
+            FmAlgorithm::OneToThree => [0b1000, 0b1000, 0b1000, 0],
+            FmAlgorithm::Additive => [0, 0, 0, 0],
+        }
+    }
+
+    /// Bitmask of the operators heard at the output.
+    pub fn carriers(self) -> u8 {
+        match self {
+            FmAlgorithm::TwoOp | FmAlgorithm::Stack | FmAlgorithm::ThreeToOne => 0b0001,
+            FmAlgorithm::TwoStacks => 0b0101,
+            FmAlgorithm::OneToThree => 0b0111,
+            FmAlgorithm::Additive => 0b1111,
+        }
+    }
+
+    /// Number of operators the algorithm uses (2 or 4).
+    pub fn operator_count(self) -> usize {
+        if self == FmAlgorithm::TwoOp { 2 } else { 4 }
+    }
+}
+
+/// One FM operator: a sine at `ratio` times the note frequency. `level` is the output
+/// level for a carrier and the modulation index (0-1 maps to 0-8 radians) for a modulator.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FmOperator {
+    pub ratio: f32,
+    pub level: f32,
+    pub envelope: EnvelopeSettings,
+}
+
+impl Default for FmOperator {
+    fn default() -> Self {
+        Self { ratio: 1.0, level: 0.0, envelope: EnvelopeSettings { attack: 0.001, decay: 0.4, sustain: 1.0, release: 0.3 } }
+    }
+}
+
+/// FM source settings. Feedback applies to the top operator of the algorithm.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FmSettings {
+    pub algorithm: FmAlgorithm,
+    pub feedback: f32,
+    pub operators: [FmOperator; 4],
+}
+
+impl Default for FmSettings {
+    fn default() -> Self {
+        let op = |ratio: f32, level: f32| FmOperator { ratio, level, ..FmOperator::default() };
+        Self {
+            algorithm: FmAlgorithm::TwoOp,
+            feedback: 0.0,
+            operators: [op(1.0, 1.0), op(1.0, 0.5), op(2.0, 0.0), op(3.0, 0.0)],
+        }
+    }
+}
+
+/// Points per wavetable cycle.
+pub const WAVETABLE_SIZE: usize = 256;
+/// Upper bound on the tables one wavetable instrument can morph through.
+pub const MAX_WAVETABLES: usize = 8;
+
+/// Wavetable source settings. `position` sweeps from the first table (0) to the last (1),
+/// crossfading between neighbours.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct WavetableSettings {
+    pub position: f32,
+}
+
+/// A sine and a saw, the starting point for a new wavetable instrument.
+pub fn default_wavetables() -> Vec<Vec<f32>> {
+    let sine = (0..WAVETABLE_SIZE).map(|i| (i as f32 / WAVETABLE_SIZE as f32 * std::f32::consts::TAU).sin()).collect();
+    let saw = (0..WAVETABLE_SIZE).map(|i| 1.0 - 2.0 * i as f32 / WAVETABLE_SIZE as f32).collect();
+    vec![sine, saw]
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DrumKind {
+    #[default]
+    Kick,
+    Snare,
+    ClosedHat,
+    OpenHat,
+    Clap,
+}
+
+impl DrumKind {
+    pub const ALL: [DrumKind; 5] = [DrumKind::Kick, DrumKind::Snare, DrumKind::ClosedHat, DrumKind::OpenHat, DrumKind::Clap];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DrumKind::Kick => "KICK",
+            DrumKind::Snare => "SNARE",
+            DrumKind::ClosedHat => "CL HAT",
+            DrumKind::OpenHat => "OP HAT",
+            DrumKind::Clap => "CLAP",
+        }
+    }
+}
+
+/// Drum source settings. Drums play at their own pitch on note 48 (C of the default octave)
+/// and follow the note from there.
+/// `tune` is in semitones, `decay` in seconds; `sweep` is the depth of the pitch drop on
+/// kick and snare, and `tone` sets the noise level (snare) or brightness (hats, clap), 0-1.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct DrumSettings {
+    pub kind: DrumKind,
+    pub tune: f32,
+    pub decay: f32,
+    pub sweep: f32,
+    pub tone: f32,
+}
+
+impl DrumSettings {
+    /// A starting point for each kind of drum.
+    pub fn new(kind: DrumKind) -> Self {
+        let (decay, sweep, tone) = match kind {
+            DrumKind::Kick => (0.45, 0.6, 0.2),
+            DrumKind::Snare => (0.2, 0.3, 0.6),
+            DrumKind::ClosedHat => (0.05, 0.0, 0.7),
+            DrumKind::OpenHat => (0.4, 0.0, 0.7),
+            DrumKind::Clap => (0.25, 0.0, 0.5),
+        };
+        Self { kind, tune: 0.0, decay, sweep, tone }
+    }
+}
+
+impl Default for DrumSettings {
+    fn default() -> Self {
+        Self::new(DrumKind::Kick)
+    }
+}
+
+/// Upper bound for per-track polyphony.
+pub const MAX_POLYPHONY: u8 = 8;
+
+/// Which held note gives way when a track runs out of polyphony.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VoiceStealing {
+    #[default]
+    Oldest,
+    Quietest,
+}
+
+impl VoiceStealing {
+    pub const ALL: [VoiceStealing; 2] = [VoiceStealing::Oldest, VoiceStealing::Quietest];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            VoiceStealing::Oldest => "OLDEST",
+            VoiceStealing::Quietest => "QUIETEST",
+        }
+    }
+}
+
+/// Notes a track may hold at once. A note pushed out by a new one still plays its release.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct VoiceSettings {
+    pub polyphony: u8,
+    pub stealing: VoiceStealing,
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self { polyphony: 1, stealing: VoiceStealing::Oldest }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+    SampleHold,
+}
+
+impl LfoShape {
+    pub const ALL: [LfoShape; 5] = [LfoShape::Sine, LfoShape::Triangle, LfoShape::Saw, LfoShape::Square, LfoShape::SampleHold];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LfoShape::Sine => "SINE",
+            LfoShape::Triangle => "TRI",
+            LfoShape::Saw => "SAW",
+            LfoShape::Square => "SQR",
+            LfoShape::SampleHold => "S&H",
+        }
+    }
+}
+
+/// A free LFO runs at `rate` Hz; a synced one takes `division` per cycle at the song tempo.
+/// A retriggered LFO restarts with each note, otherwise every voice shares one running phase.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LfoSettings {
+    pub shape: LfoShape,
+    pub rate: f32,
+    pub sync: bool,
+    pub division: DelayDivision,
+    pub retrigger: bool,
+}
+
+impl Default for LfoSettings {
+    fn default() -> Self {
+        Self {
+            shape: LfoShape::Sine,
+            rate: 4.0,
+            sync: false,
+            division: DelayDivision::Quarter,
+            retrigger: true,
+        }
+    }
+}
+
+/// Modulation sources. LFOs are bipolar, envelopes and velocity run 0-1, and note
+/// number is -1 to 1 around middle C.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ModSource {
+    #[default]
+    Off,
+    Lfo1,
+    Lfo2,
+    AmpEnvelope,
+    ModEnvelope,
+    Velocity,
+    Note,
+}
+
+impl ModSource {
+    pub const ALL: [ModSource; 7] = [
+        ModSource::Off, ModSource::Lfo1, ModSource::Lfo2, ModSource::AmpEnvelope,
+        ModSource::ModEnvelope, ModSource::Velocity, ModSource::Note,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ModSource::Off => "--",
+            ModSource::Lfo1 => "LFO1",
+            ModSource::Lfo2 => "LFO2",
+            ModSource::AmpEnvelope => "AMP ENV",
+            ModSource::ModEnvelope => "MOD ENV",
+            ModSource::Velocity => "VEL",
+            ModSource::Note => "NOTE",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ModDestination {
+    #[default]
+    Pitch,
+    Cutoff,
+    PulseWidth,
+    Pan,
+    Volume,
+}
+
+impl ModDestination {
+    pub const ALL: [ModDestination; 5] = [
+        ModDestination::Pitch, ModDestination::Cutoff, ModDestination::PulseWidth, ModDestination::Pan, ModDestination::Volume,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ModDestination::Pitch => "PITCH",
+            ModDestination::Cutoff => "CUTOFF",
+            ModDestination::PulseWidth => "PW",
+            ModDestination::Pan => "PAN",
+            ModDestination::Volume => "VOL",
+        }
+    }
+}
+
+/// One matrix slot: `amount` (-1 to 1) of `source` added to `destination`. At full amount
+/// pitch moves 12 semitones, cutoff and pan their whole range, pulse width half a cycle
+/// and volume 100%.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModRoute {
+    pub source: ModSource,
+    pub destination: ModDestination,
+    pub amount: f32,
+}
+
+/// Slots in each instrument's modulation matrix.
+pub const MOD_SLOTS: usize = 6;
+
+/// Per-instrument LFOs, a spare envelope and the matrix that routes them.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModulationSettings {
+    pub lfos: [LfoSettings; 2],
+    pub envelope: EnvelopeSettings,
+    pub routes: [ModRoute; MOD_SLOTS],
+}
+
+impl ModulationSettings {
+    /// Whether any active slot modulates `destination`.
+    pub fn routes_to(&self, destination: ModDestination) -> bool {
+        self.routes.iter().any(|r| r.source != ModSource::Off && r.destination == destination && r.amount != 0.0)
+    }
+}
+
+/// ADSR times in seconds, sustain as a 0-1 level.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct EnvelopeSettings {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Default for EnvelopeSettings {
+    fn default() -> Self {
+        Self {
+            attack: 0.005,
+            decay: 0.4,
+            sustain: 0.6,
+            release: 0.3,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FilterMode {
+    #[default]
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+}
+
+impl FilterMode {
+    pub const ALL: [FilterMode; 4] = [FilterMode::LowPass, FilterMode::HighPass, FilterMode::BandPass, FilterMode::Notch];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FilterMode::LowPass => "LP",
+            FilterMode::HighPass => "HP",
+            FilterMode::BandPass => "BP",
+            FilterMode::Notch => "NOTCH",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DriveMode {
+    #[default]
+    SoftClip,
+    HardClip,
+    Foldback,
+    Bitcrush,
+}
+
+impl DriveMode {
+    pub const ALL: [DriveMode; 4] = [DriveMode::SoftClip, DriveMode::HardClip, DriveMode::Foldback, DriveMode::Bitcrush];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DriveMode::SoftClip => "SOFT",
+            DriveMode::HardClip => "HARD",
+            DriveMode::Foldback => "FOLD",
+            DriveMode::Bitcrush => "CRUSH",
+        }
+    }
+}
+
+/// Drive is a 0-1 knob position; 0 bypasses the stage.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct DriveSettings {
+    pub mode: DriveMode,
+    pub drive: f32,
+}
+
+/// Delay time as a note length, so it follows the song tempo.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DelayDivision {
+    Half,
+    DottedQuarter,
+    Quarter,
+    #[default]
+    DottedEighth,
+    TripletQuarter,
+    Eighth,
+    TripletEighth,
+    Sixteenth,
+}
+
+impl DelayDivision {
+    pub const ALL: [DelayDivision; 8] = [
+        DelayDivision::Half, DelayDivision::DottedQuarter, DelayDivision::Quarter, DelayDivision::DottedEighth,
+        DelayDivision::TripletQuarter, DelayDivision::Eighth, DelayDivision::TripletEighth, DelayDivision::Sixteenth,
+    ];
+
+    /// Length in quarter-note beats
+    pub fn beats(self) -> f32 {
+        match self {
+            DelayDivision::Half => 2.0,
+            DelayDivision::DottedQuarter => 1.5,
+            DelayDivision::Quarter => 1.0,
+            DelayDivision::DottedEighth => 0.75,
+            DelayDivision::TripletQuarter => 2.0 / 3.0,
+            DelayDivision::Eighth => 0.5,
+            DelayDivision::TripletEighth => 1.0 / 3.0,
+            DelayDivision::Sixteenth => 0.25,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DelayDivision::Half => "1/2",
+            DelayDivision::DottedQuarter => "1/4.",
+            DelayDivision::Quarter => "1/4",
+            DelayDivision::DottedEighth => "1/8.",
+            DelayDivision::TripletQuarter => "1/4T",
+            DelayDivision::Eighth => "1/8",
+            DelayDivision::TripletEighth => "1/8T",
+            DelayDivision::Sixteenth => "1/16",
+        }
+    }
+}
+
+/// A tempo-synced delay unit on an aux bus. Feedback and damping are 0-1.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct DelaySettings {
+    pub division: DelayDivision,
+    pub feedback: f32,
+    pub damping: f32,
+    pub ping_pong: bool,
+}
+
+impl Default for DelaySettings {
+    fn default() -> Self {
+        Self {
+            division: DelayDivision::DottedEighth,
+            feedback: 0.4,
+            damping: 0.3,
+            ping_pong: true,
+        }
+    }
+}
+
+/// A reverb unit on an aux bus. Size, damping and wet level are 0-1.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ReverbSettings {
+    pub size: f32,
+    pub damping: f32,
+    pub wet: f32,
+    pub pre_delay_ms: f32,
+}
+
+impl Default for ReverbSettings {
+    fn default() -> Self {
+        Self {
+            size: 0.6,
+            damping: 0.5,
+            wet: 0.5,
+            pre_delay_ms: 20.0,
+        }
+    }
+}
+
+/// Upper bound on aux buses, which the audio engine preallocates.
+pub const MAX_AUX_BUSES: usize = 4;
+
+/// The unit an aux bus runs its sends through.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuxEffect {
+    #[default]
+    Delay,
+    Reverb,
+}
+
+impl AuxEffect {
+    pub const ALL: [AuxEffect; 2] = [AuxEffect::Delay, AuxEffect::Reverb];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AuxEffect::Delay => "DELAY",
+            AuxEffect::Reverb => "REVERB",
+        }
+    }
+}
+
+/// What the audio engine needs to run one aux bus. Both units' settings are kept so
+/// switching the effect back and forth doesn't lose either.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct AuxBusSettings {
+    pub effect: AuxEffect,
+    #[serde(default)]
+    pub delay: DelaySettings,
+    #[serde(default)]
+    pub reverb: ReverbSettings,
+    /// Level the effect output comes back into the mix at (0-1)
+    pub return_level: f32,
+    #[serde(default)]
+    pub muted: bool,
+}
+
+impl AuxBusSettings {
+    /// A bus slot with nothing on it.
+    pub const OFF: AuxBusSettings = AuxBusSettings {
+        effect: AuxEffect::Delay,
+        delay: DelaySettings {
+            division: DelayDivision::DottedEighth,
+            feedback: 0.0,
+            damping: 0.0,
+            ping_pong: false,
+        },
+        reverb: ReverbSettings {
+            size: 0.0,
+            damping: 0.0,
+            wet: 0.0,
+            pre_delay_ms: 0.0,
+        },
+        return_level: 0.0,
+        muted: true,
+    };
+
+    pub fn new(effect: AuxEffect) -> Self {
+        Self {
+            effect,
+            delay: DelaySettings::default(),
+            reverb: ReverbSettings::default(),
+            return_level: 1.0,
+            muted: false,
+        }
+    }
+}
+
+/// One aux send/return bus in the mixer. Tracks send into it by position.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AuxBus {
+    pub name: String,
+    #[serde(flatten)]
+    pub settings: AuxBusSettings,
+}
+
+impl AuxBus {
+    pub fn new(name: &str, effect: AuxEffect) -> Self {
+        Self { name: name.to_string(), settings: AuxBusSettings::new(effect) }
+    }
+}
+
+/// New projects start with one delay bus and one reverb bus, like the old fixed sends.
+pub fn default_aux_buses() -> Vec<AuxBus> {
+    vec![AuxBus::new("DELAY", AuxEffect::Delay), AuxBus::new("REVERB", AuxEffect::Reverb)]
+}
+
+/// The master bus: an optional glue compressor into a look-ahead limiter that holds
+/// peaks at `ceiling_db`. Levels are in dBFS, times in milliseconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct MasterSettings {
+    pub ceiling_db: f32,
+    pub compressor: bool,
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    pub makeup_db: f32,
+}
+
+impl Default for MasterSettings {
+    fn default() -> Self {
+        Self {
+            ceiling_db: -0.3,
+            compressor: false,
+            threshold_db: -12.0,
+            ratio: 2.0,
+            attack_ms: 10.0,
+            release_ms: 150.0,
+            makeup_db: 0.0,
+        }
+    }
+}
+
+/// Cutoff and resonance are 0-1 knob positions; cutoff maps exponentially to 20 Hz - 20 kHz.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FilterSettings {
+    pub mode: FilterMode,
+    pub cutoff: f32,
+    pub resonance: f32,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self {
+            mode: FilterMode::LowPass,
+            cutoff: 1.0,
+            resonance: 0.0,
         }
     }
 }
@@ -47,7 +948,7 @@ impl Default for TrackConfig {
 impl Default for ProjectData {
     fn default() -> Self {
         // Initialize tracks with professional names
-        let mut tracks = Vec::with_capacity(NUM_CHANNELS);
+        let mut tracks = Vec::with_capacity(DEFAULT_TRACKS);
         let track_names = vec![
             "KICK", "SNARE", "BASSLINE", "LEAD SYNTH",
             "PAD", "ARP", "PLUCK", "FX",
@@ -55,21 +956,32 @@ impl Default for ProjectData {
             "TRACK 13", "TRACK 14", "TRACK 15", "TRACK 16"
         ];
         
-        for i in 0..NUM_CHANNELS {
+        for i in 0..DEFAULT_TRACKS {
             let name = track_names.get(i)
                 .map(|s| s.to_string())
                 .unwrap_or_else(|| format!("TRACK {}", i + 1));
+            // Drums and bass start on the bundled samples and FM patch, the rest on the basic waveforms
+            let instrument = match i {
+                0 => 4,
+                1 => 5,
+                2 => 6,
+                _ => (i % 4) as u8,
+            };
             tracks.push(TrackConfig {
                 name,
                 index: i,
+                instrument,
                 ..default()
             });
         }
 
         Self {
             bpm: 140,
-            patterns: vec![Pattern::default()], 
+            patterns: vec![Pattern::new(DEFAULT_TRACKS)],
             current_pattern: 0,
+            order: default_order(),
+            current_order: 0,
+            play_mode: PlayMode::default(),
             playing: false,
             current_row: 0,
             current_channel: 0,
@@ -78,39 +990,131 @@ impl Default for ProjectData {
             current_instrument: 1,
             auto_advance: 1,
             tracks,
+            instruments: default_instruments(),
+            aux_buses: default_aux_buses(),
+            master: MasterSettings::default(),
+            track_layout: 0,
+            song_generation: 0,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+impl ProjectData {
+    /// Rows in the pattern being edited.
+    pub fn current_pattern_rows(&self) -> usize {
+        self.patterns.get(self.current_pattern).map_or(ROWS_PER_PATTERN, |p| p.rows.len()).max(1)
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// Moves on whenever the patterns or the order list may have changed.
+    pub fn song_generation(&self) -> u64 {
+        self.song_generation
+    }
+
+    /// The patterns and order list, to edit. The song counts as changed.
+    pub fn song_mut(&mut self) -> SongMut<'_> {
+        self.song_generation += 1;
+        SongMut { patterns: &mut self.patterns, order: &mut self.order }
+    }
+
+    /// One pattern, to edit; the song only counts as changed if it exists.
+    pub fn pattern_mut(&mut self, index: usize) -> Option<&mut Pattern> {
+        let pattern = self.patterns.get_mut(index)?;
+        self.song_generation += 1;
+        Some(pattern)
+    }
+
+    /// The cell at `row` of `channel`, to edit; the song only counts as changed if it exists.
+    pub fn cell_mut(&mut self, pattern: usize, row: usize, channel: usize) -> Option<&mut ChannelData> {
+        let cell = self.patterns.get_mut(pattern)?.rows.get_mut(row)?.channels.get_mut(channel)?;
+        self.song_generation += 1;
+        Some(cell)
+    }
+
+    /// Takes over a project loaded from disk. Its song and track layout count as new, so
+    /// playback resends them and the engine drops whatever the old project left on its tracks.
+    pub fn replace_with(&mut self, mut loaded: ProjectData) {
+        loaded.song_generation = self.song_generation + 1;
+        loaded.track_layout = self.track_layout + 1;
+        *self = loaded;
+    }
+}
+
+/// Whether playback walks the order list or loops the pattern being edited.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlayMode {
+    #[default]
+    Song,
+    Pattern,
+}
+
+impl PlayMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            PlayMode::Song => "SONG",
+            PlayMode::Pattern => "PATTERN",
+        }
+    }
+}
+
+/// New projects start with their one pattern as the whole song.
+pub fn default_order() -> Vec<usize> {
+    vec![0]
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Pattern {
+    #[serde(default)]
+    pub name: String,
     pub rows: Vec<Row>,
 }
 
-impl Default for Pattern {
-    fn default() -> Self {
+impl Pattern {
+    /// An empty, unnamed pattern of the default length with a column for each of `tracks`.
+    pub fn new(tracks: usize) -> Self {
         Self {
-            rows: vec![Row::default(); ROWS_PER_PATTERN],
+            name: String::new(),
+            rows: vec![Row::new(tracks); ROWS_PER_PATTERN],
         }
     }
+
+    /// Number of track columns.
+    pub fn tracks(&self) -> usize {
+        self.rows.first().map_or(0, |row| row.channels.len())
+    }
+
+    /// Changes the length to `rows` (1 to `MAX_PATTERN_ROWS`). Rows that still fit keep
+    /// their content; added rows are empty.
+    pub fn resize(&mut self, rows: usize) {
+        let tracks = self.tracks();
+        self.rows.resize(rows.clamp(1, MAX_PATTERN_ROWS), Row::new(tracks));
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Copy)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Row {
-    pub channels: [ChannelData; NUM_CHANNELS],
+    /// One cell per track, by position in `ProjectData::tracks`
+    pub channels: Vec<ChannelData>,
 }
 
-impl Default for Row {
-    fn default() -> Self {
+impl Row {
+    pub fn new(tracks: usize) -> Self {
         Self {
-            channels: [ChannelData::default(); NUM_CHANNELS],
+            channels: vec![ChannelData::default(); tracks],
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
 pub struct ChannelData {
-    pub note: Option<u8>, // MIDI Note Number (0-127)
+    pub note: Option<u8>, // MIDI Note Number (0-127) or NOTE_OFF
     pub instrument: Option<u8>,
     pub volume: Option<u8>, // 0-64
     pub effect: Option<EffectType>, 
@@ -120,10 +1124,188 @@ pub struct ChannelData {
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum EffectType {
     None,
-    PitchSlide, // 1xx
-    Arpeggio,   // 0xy
-    VolumeSlide,// Axy
-    // Add more as needed
+    #[serde(alias = "PitchSlide")]
+    PitchSlideUp,   // 1xx: up xx/16 semitone per tick
+    PitchSlideDown, // 2xx: down xx/16 semitone per tick
+    Arpeggio,   // 0xy: note, +x, +y semitones, cycling every tick
+    VolumeSlide,// Axy: up x or down y per tick, on the 0-64 volume scale
+    FilterControl, // Zxx: 00-7F cutoff, 80-8F resonance
+    Panning,    // 8xx: 00 left, 80 centre, FF right
+    TonePortamento, // 3xx: glide to the note at xx/16 semitone per tick without retriggering
+    Vibrato,    // 4xy: speed x, depth y/8 semitone
+    Tremolo,    // 7xy: speed x, depth 4y on the 0-64 volume scale
+    SampleOffset, // 9xx: start samples xx * 256 frames in
+    PositionJump, // Bxx: go to order position xx after this row
+    PatternBreak, // Dxx: go to row xx (decimal) of the next pattern in the order after this row
+    Extended,   // Exy: sub-command x with parameter y, see `ExtendedEffect`
+    SetSpeed,   // Fxx: 01-1F ticks per row, 20-FF BPM
+}
+
+impl EffectType {
+    /// Every command that can be entered, in the order they're listed in help text.
+    pub const ALL: [EffectType; 14] = [
+        EffectType::Arpeggio,
+        EffectType::PitchSlideUp,
+        EffectType::PitchSlideDown,
+        EffectType::TonePortamento,
+        EffectType::Vibrato,
+        EffectType::Tremolo,
+        EffectType::Panning,
+        EffectType::SampleOffset,
+        EffectType::VolumeSlide,
+        EffectType::PositionJump,
+        EffectType::PatternBreak,
+        EffectType::Extended,
+        EffectType::SetSpeed,
+        EffectType::FilterControl,
+    ];
+
+    /// The character typed into, and shown in, the command column.
+    pub fn command(self) -> char {
+        match self {
+            EffectType::None => '-',
+            EffectType::Arpeggio => '0',
+            EffectType::PitchSlideUp => '1',
+            EffectType::PitchSlideDown => '2',
+            EffectType::TonePortamento => '3',
+            EffectType::Vibrato => '4',
+            EffectType::Tremolo => '7',
+            EffectType::Panning => '8',
+            EffectType::SampleOffset => '9',
+            EffectType::VolumeSlide => 'A',
+            EffectType::PositionJump => 'B',
+            EffectType::PatternBreak => 'D',
+            EffectType::Extended => 'E',
+            EffectType::SetSpeed => 'F',
+            EffectType::FilterControl => 'Z',
+        }
+    }
+
+    pub fn from_command(command: char) -> Option<EffectType> {
+        let command = command.to_ascii_uppercase();
+        Self::ALL.into_iter().find(|effect| effect.command() == command)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EffectType::None => "None",
+            EffectType::Arpeggio => "Arpeggio",
+            EffectType::PitchSlideUp => "Pitch slide up",
+            EffectType::PitchSlideDown => "Pitch slide down",
+            EffectType::TonePortamento => "Tone portamento",
+            EffectType::Vibrato => "Vibrato",
+            EffectType::Tremolo => "Tremolo",
+            EffectType::Panning => "Pan",
+            EffectType::SampleOffset => "Sample offset",
+            EffectType::VolumeSlide => "Volume slide",
+            EffectType::PositionJump => "Position jump",
+            EffectType::PatternBreak => "Pattern break",
+            EffectType::Extended => "Extended",
+            EffectType::SetSpeed => "Set speed/tempo",
+            EffectType::FilterControl => "Filter",
+        }
+    }
+
+    /// What a command with this parameter does, naming the sub-command for `Exy`.
+    pub fn describe(self, value: u8) -> &'static str {
+        match (self, ExtendedEffect::parse(value)) {
+            (EffectType::Extended, Some((extended, _))) => extended.name(),
+            _ => self.name(),
+        }
+    }
+}
+
+/// The `Exy` sub-commands, picked by the high digit; the low digit is the parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtendedEffect {
+    FineSlideUp,    // E1x: up x/16 semitone once
+    FineSlideDown,  // E2x: down x/16 semitone once
+    PatternLoop,    // E6x: E60 marks the start, E6x plays back to it x times
+    Retrigger,      // E9x: restart the note every x ticks
+    FineVolumeUp,   // EAx: up x once
+    FineVolumeDown, // EBx: down x once
+    NoteCut,        // ECx: silence the note on tick x
+    NoteDelay,      // EDx: hold the note back until tick x
+}
+
+impl ExtendedEffect {
+    pub const ALL: [ExtendedEffect; 8] = [
+        ExtendedEffect::FineSlideUp,
+        ExtendedEffect::FineSlideDown,
+        ExtendedEffect::PatternLoop,
+        ExtendedEffect::Retrigger,
+        ExtendedEffect::FineVolumeUp,
+        ExtendedEffect::FineVolumeDown,
+        ExtendedEffect::NoteCut,
+        ExtendedEffect::NoteDelay,
+    ];
+
+    /// The high digit of the `Exy` value.
+    pub fn digit(self) -> u8 {
+        match self {
+            ExtendedEffect::FineSlideUp => 0x1,
+            ExtendedEffect::FineSlideDown => 0x2,
+            ExtendedEffect::PatternLoop => 0x6,
+            ExtendedEffect::Retrigger => 0x9,
+            ExtendedEffect::FineVolumeUp => 0xA,
+            ExtendedEffect::FineVolumeDown => 0xB,
+            ExtendedEffect::NoteCut => 0xC,
+            ExtendedEffect::NoteDelay => 0xD,
+        }
+    }
+
+    /// Splits an `Exy` value into its sub-command and parameter.
+    pub fn parse(value: u8) -> Option<(ExtendedEffect, u8)> {
+        let command = Self::ALL.into_iter().find(|effect| effect.digit() == value >> 4)?;
+        Some((command, value & 0x0F))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExtendedEffect::FineSlideUp => "Fine slide up",
+            ExtendedEffect::FineSlideDown => "Fine slide down",
+            ExtendedEffect::PatternLoop => "Pattern loop",
+            ExtendedEffect::Retrigger => "Retrigger",
+            ExtendedEffect::FineVolumeUp => "Fine volume up",
+            ExtendedEffect::FineVolumeDown => "Fine volume down",
+            ExtendedEffect::NoteCut => "Note cut",
+            ExtendedEffect::NoteDelay => "Note delay",
+        }
+    }
+}
+
+/// Fxx values below this set ticks per row, the rest set the tempo.
+pub const SPEED_TEMPO_SPLIT: u8 = 0x20;
+/// The tempo range Fxx can set, and so the range the BPM control covers.
+pub const MIN_BPM: u32 = SPEED_TEMPO_SPLIT as u32;
+pub const MAX_BPM: u32 = 0xFF;
+
+/// Dxx rows are written in decimal, as in ProTracker: D32 is row 32.
+pub fn break_row(value: u8) -> usize {
+    (value >> 4) as usize * 10 + (value & 0x0F) as usize
+}
+
+/// A cell's effect as it reads in the grid, e.g. "A0F". Blank cells have none.
+pub fn effect_text(cell: &ChannelData) -> Option<String> {
+    match cell.effect {
+        Some(EffectType::None) | None => None,
+        Some(effect) => Some(format!("{}{:02X}", effect.command(), cell.effect_value.unwrap_or(0))),
+    }
+}
+
+/// Zxx values at or above this set resonance instead of cutoff
+pub const FILTER_RESONANCE_BASE: u8 = 0x80;
+
+/// The 8xx value for a pan position (-1 to 1), and back.
+pub fn pan_to_effect(pan: f32) -> u8 {
+    let pan = pan.clamp(-1.0, 1.0);
+    let offset = if pan < 0.0 { pan * 128.0 } else { pan * 127.0 };
+    (128.0 + offset).round() as u8
+}
+
+pub fn effect_to_pan(value: u8) -> f32 {
+    let offset = value as f32 - 128.0;
+    if offset < 0.0 { offset / 128.0 } else { offset / 127.0 }
 }
 
 // Marker component for valid "Visual" note if we need to spawn entities (optional in data-driven UI)
@@ -1,5 +1,6 @@
 use bevy::prelude::*;
-use crate::data::{ProjectData, ROWS_PER_PATTERN, NUM_CHANNELS};
+use bevy_egui::EguiContexts;
+use crate::data::{ChannelData, EffectType, Pattern, ProjectData, TrackConfig, MAX_TRACKS, NOTE_OFF};
 use crate::audio_engine::{AudioCommand, AudioEngine, MidiInputEvent};
 use crate::matrix_visuals::VisualNoteEvent;
 
@@ -10,40 +11,78 @@ pub enum EditMode {
     Edit,
 }
 
+/// Which part of the cell under the cursor typing goes into. TAB moves through them.
+#[derive(Resource, Default, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EditField {
+    #[default]
+    Note,
+    /// The instrument number's high (0) and low (1) hex digits
+    Instrument(u8),
+    /// The effect command (0), then its high (1) and low (2) hex digits
+    Effect(u8),
+}
+
+const MIDI_CC_CUTOFF: u8 = 74;
+const MIDI_CC_RESONANCE: u8 = 71;
+
 pub struct EditorPlugin;
 
 impl Plugin for EditorPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ProjectData>()
            .init_resource::<EditMode>()
+           .init_resource::<EditField>()
            .add_systems(Update, (handle_keyboard, handle_midi_input));
     }
 }
 
 // ...
 
+#[allow(clippy::too_many_arguments)] // Bevy system parameters
 fn handle_keyboard(
     mut project: ResMut<ProjectData>,
     mut edit_mode: ResMut<EditMode>,
+    mut edit_field: ResMut<EditField>,
     keyboard: Res<ButtonInput<KeyCode>>,
     audio: Res<AudioEngine>,
     mut visual_events: EventWriter<VisualNoteEvent>,
+    mut preview_note: Local<Option<(usize, u8)>>,
+    mut contexts: EguiContexts,
 ) {
+    // Keys typed into a text field (e.g. a sample path) aren't notes
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
     // ... (Navigation, Play/Stop, Mode Toggle unchanged)
 
+    // Releasing a note key releases the previewed note
+    if let Some((ch, note)) = *preview_note {
+        if keyboard.get_just_released().any(|k| key_to_note(Some(k)).is_some()) {
+            audio.send(AudioCommand::StopNote { channel: ch, note: Some(note) });
+            *preview_note = None;
+        }
+    }
+
+    // 0. Pattern management (CTRL held, so the letters aren't notes)
+    if keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        handle_pattern_shortcuts(&mut project, &keyboard);
+        return;
+    }
+
     // 1. Navigation
     if keyboard.just_pressed(KeyCode::ArrowUp) {
         if project.current_row > 0 { project.current_row -= 1; }
-        else { project.current_row = ROWS_PER_PATTERN - 1; }
+        else { project.current_row = project.current_pattern_rows() - 1; }
     }
     if keyboard.just_pressed(KeyCode::ArrowDown) {
-        project.current_row = (project.current_row + 1) % ROWS_PER_PATTERN;
+        project.current_row = (project.current_row + 1) % project.current_pattern_rows();
     }
-    if keyboard.just_pressed(KeyCode::ArrowLeft) {
-        if project.current_channel > 0 { project.current_channel -= 1; }
+    if keyboard.just_pressed(KeyCode::ArrowLeft) && project.current_channel > 0 {
+        project.current_channel -= 1;
     }
-    if keyboard.just_pressed(KeyCode::ArrowRight) {
-        if project.current_channel < NUM_CHANNELS - 1 { project.current_channel += 1; }
+    if keyboard.just_pressed(KeyCode::ArrowRight) && project.current_channel + 1 < project.tracks.len() {
+        project.current_channel += 1;
     }
 
     // 2. Play/Stop
@@ -59,45 +98,87 @@ fn handle_keyboard(
         };
     }
 
-    // 4. Note Entry (Only in Edit Mode)
+    // 4. Note, Instrument and Effect Entry (Only in Edit Mode)
+    if *edit_mode == EditMode::Edit && keyboard.just_pressed(KeyCode::Tab) {
+        *edit_field = match *edit_field {
+            EditField::Note => EditField::Instrument(0),
+            EditField::Instrument(_) => EditField::Effect(0),
+            EditField::Effect(_) => EditField::Note,
+        };
+    }
     if *edit_mode == EditMode::Edit {
+        if let EditField::Instrument(digit) = *edit_field {
+            enter_instrument(&mut project, &mut edit_field, digit, keyboard.get_just_pressed().find_map(key_to_char));
+            if keyboard.just_pressed(KeyCode::Backspace) || keyboard.just_pressed(KeyCode::Delete) {
+                let (pattern_idx, row_idx, ch_idx) = (project.current_pattern, project.current_row, project.current_channel);
+                if let Some(cell) = project.cell_mut(pattern_idx, row_idx, ch_idx) {
+                    cell.instrument = None;
+                }
+                *edit_field = EditField::Instrument(0);
+            }
+            return;
+        }
+
+        if let EditField::Effect(digit) = *edit_field {
+            enter_effect(&mut project, &mut edit_field, digit, keyboard.get_just_pressed().find_map(key_to_char));
+            if keyboard.just_pressed(KeyCode::Backspace) || keyboard.just_pressed(KeyCode::Delete) {
+                let (pattern_idx, row_idx, ch_idx) = (project.current_pattern, project.current_row, project.current_channel);
+                if let Some(cell) = project.cell_mut(pattern_idx, row_idx, ch_idx) {
+                    cell.effect = None;
+                    cell.effect_value = None;
+                }
+                *edit_field = EditField::Effect(0);
+            }
+            return;
+        }
+
         if let Some(note_offset) = key_to_note(keyboard.get_just_pressed().next()) {
             let octave = project.current_octave;
-            let note = (octave as u8 * 12) + note_offset;
+            let note = (octave * 12) + note_offset;
             
-            let current_inst = project.current_instrument;
+            // New notes get the instrument selected in the bank
+            let inst = project.current_instrument;
             let auto_advance = project.auto_advance;
 
             let pattern_idx = project.current_pattern;
             let row_idx = project.current_row;
             let ch_idx = project.current_channel;
-            
-            if pattern_idx < project.patterns.len() {
-                // Get track instrument before borrowing cell
-                let inst = if let Some(track) = project.tracks.get(ch_idx) {
-                    track.index as u8
-                } else {
-                    current_inst
-                };
-                
-                let cell = &mut project.patterns[pattern_idx].rows[row_idx].channels[ch_idx];
+
+            if let Some(cell) = project.cell_mut(pattern_idx, row_idx, ch_idx) {
                 cell.note = Some(note);
                 cell.instrument = Some(inst);
                 cell.volume = Some(64);
 
-                audio.sender.send(AudioCommand::PlayNote {
+                audio.send(AudioCommand::PlayNote {
                     note,
                     instrument: inst,
                     velocity: 127,
                     channel: ch_idx, 
-                }).ok();
+                });
 
                 visual_events.send(VisualNoteEvent {
                     note_name: note_name(note),
                     channel: ch_idx,
                 });
+                *preview_note = Some((ch_idx, note));
+
+                project.current_row = (row_idx + auto_advance) % project.current_pattern_rows();
+            }
+        }
+
+        // Note Off
+        if keyboard.just_pressed(KeyCode::Digit1) {
+            let pattern_idx = project.current_pattern;
+            let row_idx = project.current_row;
+            let ch_idx = project.current_channel;
+            let auto_advance = project.auto_advance;
+            if let Some(cell) = project.cell_mut(pattern_idx, row_idx, ch_idx) {
+                cell.note = Some(NOTE_OFF);
+                cell.instrument = None;
+                cell.volume = None;
 
-                project.current_row = (row_idx + auto_advance) % ROWS_PER_PATTERN;
+                audio.send(AudioCommand::StopNote { channel: ch_idx, note: None });
+                project.current_row = (row_idx + auto_advance) % project.current_pattern_rows();
             }
         }
         
@@ -106,8 +187,7 @@ fn handle_keyboard(
              let pattern_idx = project.current_pattern;
              let row_idx = project.current_row;
              let ch_idx = project.current_channel;
-             if pattern_idx < project.patterns.len() {
-                 let cell = &mut project.patterns[pattern_idx].rows[row_idx].channels[ch_idx];
+             if let Some(cell) = project.cell_mut(pattern_idx, row_idx, ch_idx) {
                  cell.note = None;
                  cell.instrument = None;
                  cell.volume = None;
@@ -116,6 +196,257 @@ fn handle_keyboard(
     }
 }
 
+/// CTRL+N new, CTRL+D duplicate, CTRL+DELETE delete, CTRL+←/→ previous/next pattern,
+/// CTRL+SHIFT+←/→ move the current pattern down/up the list.
+fn handle_pattern_shortcuts(project: &mut ProjectData, keyboard: &ButtonInput<KeyCode>) {
+    let current = project.current_pattern;
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keyboard.just_pressed(KeyCode::KeyN) {
+        new_pattern(project);
+    }
+    if keyboard.just_pressed(KeyCode::KeyD) {
+        duplicate_pattern(project, current);
+    }
+    if keyboard.just_pressed(KeyCode::Delete) || keyboard.just_pressed(KeyCode::Backspace) {
+        delete_pattern(project, current);
+    }
+    if keyboard.just_pressed(KeyCode::ArrowLeft) && current > 0 {
+        if shift {
+            move_pattern(project, current, current - 1);
+        } else {
+            select_pattern(project, current - 1);
+        }
+    }
+    if keyboard.just_pressed(KeyCode::ArrowRight) && current + 1 < project.patterns().len() {
+        if shift {
+            move_pattern(project, current, current + 1);
+        } else {
+            select_pattern(project, current + 1);
+        }
+    }
+}
+
+/// Opens a pattern in the grid. While playing, the song position keeps control of it.
+pub fn select_pattern(project: &mut ProjectData, index: usize) {
+    if index < project.patterns().len() && !project.playing {
+        project.current_pattern = index;
+        clamp_cursor(project);
+    }
+}
+
+/// Keeps the cursor on a pattern, order position, row and track that exist, after the
+/// pattern under it changed or shrank or a project was loaded.
+pub fn clamp_cursor(project: &mut ProjectData) {
+    project.current_pattern = project.current_pattern.min(project.patterns().len().saturating_sub(1));
+    project.current_order = project.current_order.min(project.order().len().saturating_sub(1));
+    project.current_row = project.current_row.min(project.current_pattern_rows() - 1);
+    project.current_channel = project.current_channel.min(project.tracks.len().saturating_sub(1));
+}
+
+/// Adds an empty pattern at the end of the list and opens it. It isn't in the order list until placed there.
+pub fn new_pattern(project: &mut ProjectData) {
+    let index = project.patterns().len();
+    let tracks = project.tracks.len();
+    insert_pattern(project, index, Pattern::new(tracks));
+}
+
+/// Copies a pattern into the slot after it and opens the copy.
+pub fn duplicate_pattern(project: &mut ProjectData, index: usize) {
+    if let Some(copy) = project.patterns().get(index).cloned() {
+        insert_pattern(project, index + 1, copy);
+    }
+}
+
+/// Inserts at `index`, shifting the order list so it still plays the same patterns.
+fn insert_pattern(project: &mut ProjectData, index: usize, pattern: Pattern) {
+    let song = project.song_mut();
+    song.patterns.insert(index, pattern);
+    for entry in song.order.iter_mut().filter(|entry| **entry >= index) {
+        *entry += 1;
+    }
+    project.current_pattern = index;
+    clamp_cursor(project);
+}
+
+/// Removes a pattern and every order position that played it. The last pattern is
+/// never removed, only cleared, so there is always something to edit.
+pub fn delete_pattern(project: &mut ProjectData, index: usize) {
+    if index >= project.patterns().len() {
+        return;
+    }
+    let tracks = project.tracks.len();
+    if project.patterns().len() == 1 {
+        let pattern = &mut project.song_mut().patterns[0];
+        *pattern = Pattern { name: pattern.name.clone(), ..Pattern::new(tracks) };
+        return;
+    }
+
+    // Positions before the current one that go with it shift it back
+    let removed_before = project.order().iter().take(project.current_order).filter(|&&entry| entry == index).count();
+    project.current_order -= removed_before;
+    let song = project.song_mut();
+    song.patterns.remove(index);
+    song.order.retain(|&entry| entry != index);
+    for entry in song.order.iter_mut().filter(|entry| **entry > index) {
+        *entry -= 1;
+    }
+    if song.order.is_empty() {
+        song.order.push(0);
+    }
+    project.current_order = project.current_order.min(project.order().len() - 1);
+    if project.current_pattern > index || project.current_pattern >= project.patterns().len() {
+        project.current_pattern = project.current_pattern.saturating_sub(1);
+    }
+    clamp_cursor(project);
+}
+
+/// Moves a pattern to another slot in the list; the order list follows it.
+pub fn move_pattern(project: &mut ProjectData, from: usize, to: usize) {
+    let len = project.patterns().len();
+    if from >= len || to >= len || from == to {
+        return;
+    }
+    let song = project.song_mut();
+    let pattern = song.patterns.remove(from);
+    song.patterns.insert(to, pattern);
+
+    for entry in song.order.iter_mut() {
+        *entry = moved_index(*entry, from, to);
+    }
+    project.current_pattern = moved_index(project.current_pattern, from, to);
+}
+
+/// Where `index` ends up after the item at `from` is moved to `to`.
+fn moved_index(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < to && index > from && index <= to {
+        index - 1
+    } else if to < from && index >= to && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+/// Sets a pattern's length, keeping the rows that still fit.
+pub fn resize_pattern(project: &mut ProjectData, index: usize, rows: usize) {
+    if let Some(pattern) = project.pattern_mut(index) {
+        pattern.resize(rows);
+        clamp_cursor(project);
+    }
+}
+
+pub fn rename_pattern(project: &mut ProjectData, index: usize, name: &str) {
+    if let Some(pattern) = project.pattern_mut(index) {
+        pattern.name = name.to_string();
+    }
+}
+
+/// Adds a track after the last one, with an empty column in every pattern.
+pub fn add_track(project: &mut ProjectData) {
+    let index = project.tracks.len();
+    if index >= MAX_TRACKS {
+        return;
+    }
+    project.tracks.push(TrackConfig {
+        name: format!("TRACK {}", index + 1),
+        index,
+        instrument: project.current_instrument,
+        ..default()
+    });
+    for row in project.song_mut().patterns.iter_mut().flat_map(|p| p.rows.iter_mut()) {
+        row.channels.push(ChannelData::default());
+    }
+}
+
+/// Removes a track and its column from every pattern. There is always at least one track.
+pub fn remove_track(project: &mut ProjectData, index: usize) {
+    if index >= project.tracks.len() || project.tracks.len() == 1 {
+        return;
+    }
+    project.tracks.remove(index);
+    for row in project.song_mut().patterns.iter_mut().flat_map(|p| p.rows.iter_mut()) {
+        row.channels.remove(index);
+    }
+    renumber_tracks(project);
+    project.track_layout += 1;
+    if project.current_channel > index || project.current_channel >= project.tracks.len() {
+        project.current_channel = project.current_channel.saturating_sub(1);
+    }
+}
+
+/// Moves a track to another position; its column moves with it in every pattern.
+pub fn move_track(project: &mut ProjectData, from: usize, to: usize) {
+    let len = project.tracks.len();
+    if from >= len || to >= len || from == to {
+        return;
+    }
+    let track = project.tracks.remove(from);
+    project.tracks.insert(to, track);
+    for row in project.song_mut().patterns.iter_mut().flat_map(|p| p.rows.iter_mut()) {
+        let cell = row.channels.remove(from);
+        row.channels.insert(to, cell);
+    }
+    renumber_tracks(project);
+    project.track_layout += 1;
+    project.current_channel = moved_index(project.current_channel, from, to);
+}
+
+fn renumber_tracks(project: &mut ProjectData) {
+    for (i, track) in project.tracks.iter_mut().enumerate() {
+        track.index = i;
+    }
+}
+
+/// Hex entry into the instrument column: two digits, then the cursor moves on to the next row.
+fn enter_instrument(project: &mut ProjectData, edit_field: &mut EditField, digit: u8, typed: Option<char>) {
+    let Some(nibble) = typed.and_then(|c| c.to_digit(16)) else { return };
+    let (pattern_idx, row_idx, ch_idx) = (project.current_pattern, project.current_row, project.current_channel);
+    let Some(cell) = project.cell_mut(pattern_idx, row_idx, ch_idx) else { return };
+
+    let value = cell.instrument.unwrap_or(0);
+    let value = if digit == 0 {
+        *edit_field = EditField::Instrument(1);
+        (value & 0x0F) | (nibble as u8) << 4
+    } else {
+        *edit_field = EditField::Instrument(0);
+        (value & 0xF0) | nibble as u8
+    };
+    cell.instrument = Some(value);
+    if digit != 0 {
+        project.current_row = (row_idx + project.auto_advance) % project.current_pattern_rows();
+    }
+}
+
+/// Hex entry into the effect column: a command character, then two digits for its
+/// parameter. The cursor moves on to the next row once the last digit is in.
+fn enter_effect(project: &mut ProjectData, edit_field: &mut EditField, digit: u8, typed: Option<char>) {
+    let Some(typed) = typed else { return };
+    let (pattern_idx, row_idx, ch_idx) = (project.current_pattern, project.current_row, project.current_channel);
+    let Some(cell) = project.cell_mut(pattern_idx, row_idx, ch_idx) else { return };
+
+    if digit == 0 {
+        if let Some(effect) = EffectType::from_command(typed) {
+            cell.effect = Some(effect);
+            cell.effect_value.get_or_insert(0);
+            *edit_field = EditField::Effect(1);
+        }
+        return;
+    }
+
+    let Some(nibble) = typed.to_digit(16) else { return };
+    let value = cell.effect_value.unwrap_or(0);
+    if digit == 1 {
+        cell.effect_value = Some((value & 0x0F) | (nibble as u8) << 4);
+        *edit_field = EditField::Effect(2);
+    } else {
+        cell.effect_value = Some((value & 0xF0) | nibble as u8);
+        *edit_field = EditField::Effect(0);
+        project.current_row = (row_idx + project.auto_advance) % project.current_pattern_rows();
+    }
+}
+
 fn handle_midi_input(
     mut events: EventReader<MidiInputEvent>,
     mut project: ResMut<ProjectData>,
@@ -123,56 +454,115 @@ fn handle_midi_input(
     audio: Res<AudioEngine>,
     mut visual_events: EventWriter<VisualNoteEvent>,
 ) {
-    if *edit_mode != EditMode::Edit { return; }
-
     for event in events.read() {
-        if event.status == 0x90 && event.velocity > 0 { // Note On
+        // Controllers work in any mode so the filter can be played live
+        if event.status == 0xB0 {
+            apply_midi_cc(&mut project, event.note, event.velocity);
+            continue;
+        }
+        if *edit_mode != EditMode::Edit { continue; }
+
+        let note_off = event.status == 0x80 || (event.status == 0x90 && event.velocity == 0);
+        if note_off {
+            audio.send(AudioCommand::StopNote { channel: project.current_channel, note: Some(event.note) });
+        } else if event.status == 0x90 { // Note On
             let note = event.note;
             
-            let current_inst = project.current_instrument;
+            // New notes get the instrument selected in the bank
+            let inst = project.current_instrument;
             let auto_advance = project.auto_advance;
 
             let pattern_idx = project.current_pattern;
             let row_idx = project.current_row;
             let ch_idx = project.current_channel;
-            
-             if pattern_idx < project.patterns.len() {
-                // Get track instrument before borrowing cell
-                let inst = if let Some(track) = project.tracks.get(ch_idx) {
-                    track.index as u8
-                } else {
-                    current_inst
-                };
-                
-                let cell = &mut project.patterns[pattern_idx].rows[row_idx].channels[ch_idx];
+
+            if let Some(cell) = project.cell_mut(pattern_idx, row_idx, ch_idx) {
                 cell.note = Some(note);
                 cell.instrument = Some(inst);
                 cell.volume = Some(event.velocity / 2);
 
-                audio.sender.send(AudioCommand::PlayNote {
+                audio.send(AudioCommand::PlayNote {
                     note,
                     instrument: inst,
                     velocity: event.velocity,
                     channel: ch_idx, 
-                }).ok();
+                });
 
                 visual_events.send(VisualNoteEvent {
                     note_name: note_name(note),
                     channel: ch_idx,
                 });
 
-                project.current_row = (row_idx + auto_advance) % ROWS_PER_PATTERN;
+                project.current_row = (row_idx + auto_advance) % project.current_pattern_rows();
             }
         }
     }
 }
 
+/// CC 74 (brightness) and CC 71 (timbre) drive the filter of the current track's instrument.
+fn apply_midi_cc(project: &mut ProjectData, controller: u8, value: u8) {
+    let Some(track) = project.tracks.get(project.current_channel) else { return };
+    let index = track.instrument as usize;
+    let Some(instrument) = project.instruments.get_mut(index) else { return };
+    let filter = &mut instrument.settings.filter;
+    let value = value.min(127) as f32 / 127.0;
+    match controller {
+        MIDI_CC_CUTOFF => filter.cutoff = value,
+        MIDI_CC_RESONANCE => filter.resonance = value,
+        _ => {},
+    }
+}
+
 fn note_name(midi_note: u8) -> String {
     let notes = ["C-", "C#", "D-", "D#", "E-", "F-", "F#", "G-", "G#", "A-", "A#", "B-"];
     let note_idx = (midi_note % 12) as usize;
     notes[note_idx].to_string()
 }
 
+/// The hex digit or command letter a key types in the effect column.
+fn key_to_char(key: &KeyCode) -> Option<char> {
+    let c = match key {
+        KeyCode::Digit0 => '0',
+        KeyCode::Digit1 => '1',
+        KeyCode::Digit2 => '2',
+        KeyCode::Digit3 => '3',
+        KeyCode::Digit4 => '4',
+        KeyCode::Digit5 => '5',
+        KeyCode::Digit6 => '6',
+        KeyCode::Digit7 => '7',
+        KeyCode::Digit8 => '8',
+        KeyCode::Digit9 => '9',
+        KeyCode::KeyA => 'A',
+        KeyCode::KeyB => 'B',
+        KeyCode::KeyC => 'C',
+        KeyCode::KeyD => 'D',
+        KeyCode::KeyE => 'E',
+        KeyCode::KeyF => 'F',
+        KeyCode::KeyG => 'G',
+        KeyCode::KeyH => 'H',
+        KeyCode::KeyI => 'I',
+        KeyCode::KeyJ => 'J',
+        KeyCode::KeyK => 'K',
+        KeyCode::KeyL => 'L',
+        KeyCode::KeyM => 'M',
+        KeyCode::KeyN => 'N',
+        KeyCode::KeyO => 'O',
+        KeyCode::KeyP => 'P',
+        KeyCode::KeyQ => 'Q',
+        KeyCode::KeyR => 'R',
+        KeyCode::KeyS => 'S',
+        KeyCode::KeyT => 'T',
+        KeyCode::KeyU => 'U',
+        KeyCode::KeyV => 'V',
+        KeyCode::KeyW => 'W',
+        KeyCode::KeyX => 'X',
+        KeyCode::KeyY => 'Y',
+        KeyCode::KeyZ => 'Z',
+        _ => return None,
+    };
+    Some(c)
+}
+
 fn key_to_note(key: Option<&KeyCode>) -> Option<u8> {
     match key {
         Some(KeyCode::KeyZ) => Some(0),
@@ -191,3 +581,205 @@ fn key_to_note(key: Option<&KeyCode>) -> Option<u8> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::ROWS_PER_PATTERN;
+
+    /// A project whose patterns are named after their starting index.
+    fn project(patterns: usize, order: &[usize]) -> ProjectData {
+        let mut project = ProjectData::default();
+        let tracks = project.tracks.len();
+        let song = project.song_mut();
+        *song.patterns = (0..patterns)
+            .map(|i| Pattern { name: i.to_string(), ..Pattern::new(tracks) })
+            .collect();
+        *song.order = order.to_vec();
+        project
+    }
+
+    fn names(project: &ProjectData) -> Vec<&str> {
+        project.patterns().iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn deleting_a_pattern_drops_every_position_that_played_it() {
+        let mut project = project(3, &[0, 1, 2, 1, 0]);
+        project.current_order = 3;
+        delete_pattern(&mut project, 1);
+
+        assert_eq!(names(&project), ["0", "2"]);
+        assert_eq!(project.order(), [0, 1, 0]);
+        // Position 3 went with the pattern, so the cursor lands on what followed it
+        assert_eq!(project.current_order, 2);
+    }
+
+    #[test]
+    fn deleting_the_only_pattern_clears_it() {
+        let mut project = project(1, &[0, 0]);
+        project.cell_mut(0, 0, 0).unwrap().note = Some(60);
+        delete_pattern(&mut project, 0);
+
+        assert_eq!(names(&project), ["0"]);
+        assert_eq!(project.patterns()[0].rows[0].channels[0].note, None);
+        assert_eq!(project.order(), [0, 0]);
+    }
+
+    #[test]
+    fn deleting_the_last_pattern_in_the_list_opens_the_one_before() {
+        let mut project = project(3, &[2]);
+        project.current_pattern = 2;
+        delete_pattern(&mut project, 2);
+
+        assert_eq!(names(&project), ["0", "1"]);
+        assert_eq!(project.current_pattern, 1);
+        // An order list left empty still plays something
+        assert_eq!(project.order(), [0]);
+        assert_eq!(project.current_order, 0);
+    }
+
+    #[test]
+    fn duplicating_a_pattern_shifts_the_order_entries_after_it() {
+        let mut project = project(3, &[0, 1, 2]);
+        duplicate_pattern(&mut project, 0);
+
+        assert_eq!(names(&project), ["0", "0", "1", "2"]);
+        assert_eq!(project.order(), [0, 2, 3]);
+        assert_eq!(project.current_pattern, 1);
+    }
+
+    #[test]
+    fn moving_a_pattern_remaps_the_order_list() {
+        let mut project = project(3, &[0, 1, 2, 0]);
+        move_pattern(&mut project, 0, 2);
+
+        assert_eq!(names(&project), ["1", "2", "0"]);
+        // The song still plays the same patterns in the same order
+        assert_eq!(project.order(), [2, 0, 1, 2]);
+        assert_eq!(project.current_pattern, 2);
+
+        move_pattern(&mut project, 2, 0);
+        assert_eq!(names(&project), ["0", "1", "2"]);
+        assert_eq!(project.order(), [0, 1, 2, 0]);
+    }
+
+    fn assert_rows_match_tracks(project: &ProjectData) {
+        let tracks = project.tracks.len();
+        for row in project.patterns().iter().flat_map(|p| p.rows.iter()) {
+            assert_eq!(row.channels.len(), tracks);
+        }
+        for (i, track) in project.tracks.iter().enumerate() {
+            assert_eq!(track.index, i);
+        }
+    }
+
+    #[test]
+    fn adding_and_removing_tracks_keeps_rows_in_step() {
+        let mut project = project(2, &[0, 1]);
+        add_track(&mut project);
+        assert_eq!(project.tracks.len(), 17);
+        assert_rows_match_tracks(&project);
+
+        project.cell_mut(1, 0, 5).unwrap().note = Some(60);
+        remove_track(&mut project, 3);
+        assert_eq!(project.tracks.len(), 16);
+        assert_rows_match_tracks(&project);
+        // Columns after the removed one move left with their tracks
+        assert_eq!(project.patterns()[1].rows[0].channels[4].note, Some(60));
+    }
+
+    #[test]
+    fn track_count_stays_within_bounds() {
+        let mut project = project(1, &[0]);
+        for _ in 0..MAX_TRACKS {
+            add_track(&mut project);
+        }
+        assert_eq!(project.tracks.len(), MAX_TRACKS);
+
+        for _ in 0..MAX_TRACKS {
+            remove_track(&mut project, 0);
+        }
+        assert_eq!(project.tracks.len(), 1);
+        assert_rows_match_tracks(&project);
+    }
+
+    #[test]
+    fn moving_a_track_moves_its_column_and_the_cursor() {
+        let mut project = project(1, &[0]);
+        project.tracks[2].name = "MOVED".to_string();
+        project.cell_mut(0, 0, 2).unwrap().note = Some(60);
+        project.current_channel = 2;
+        move_track(&mut project, 2, 6);
+
+        assert_rows_match_tracks(&project);
+        assert_eq!(project.tracks[6].name, "MOVED");
+        assert_eq!(project.patterns()[0].rows[0].channels[6].note, Some(60));
+        assert_eq!(project.current_channel, 6);
+
+        // The cursor stays on its track when another one moves past it
+        move_track(&mut project, 0, 8);
+        assert_eq!(project.current_channel, 5);
+        assert_eq!(project.tracks[5].name, "MOVED");
+    }
+
+    #[test]
+    fn song_edits_bump_the_generation() {
+        let mut project = project(2, &[0, 1]);
+        let mut generation = project.song_generation();
+        let edits: [fn(&mut ProjectData); 7] = [
+            new_pattern,
+            |p| delete_pattern(p, 0),
+            |p| move_pattern(p, 0, 1),
+            |p| resize_pattern(p, 0, 16),
+            add_track,
+            |p| remove_track(p, 0),
+            |p| move_track(p, 0, 1),
+        ];
+        for edit in edits {
+            edit(&mut project);
+            assert!(project.song_generation() > generation);
+            generation = project.song_generation();
+        }
+
+        // Edits that miss leave the song as it was
+        assert!(project.cell_mut(0, ROWS_PER_PATTERN, 0).is_none());
+        resize_pattern(&mut project, 5, 16);
+        assert_eq!(project.song_generation(), generation);
+    }
+
+    #[test]
+    fn effects_are_entered_as_a_command_and_two_hex_digits() {
+        for effect in EffectType::ALL {
+            let mut project = project(1, &[0]);
+            let mut field = EditField::Effect(0);
+            enter_effect(&mut project, &mut field, 0, Some('G'));
+            assert_eq!(field, EditField::Effect(0));
+
+            let typed = effect.command().to_ascii_lowercase();
+            for (digit, key) in [typed, '3', 'c'].into_iter().enumerate() {
+                enter_effect(&mut project, &mut field, digit as u8, Some(key));
+            }
+            let cell = project.patterns()[0].rows[0].channels[0];
+            assert_eq!((cell.effect, cell.effect_value), (Some(effect), Some(0x3C)));
+            assert_eq!(field, EditField::Effect(0));
+            assert_eq!(project.current_row, project.auto_advance);
+        }
+    }
+
+    #[test]
+    fn moved_index_at_the_edges() {
+        // The moved item itself
+        assert_eq!(moved_index(0, 0, 3), 3);
+        assert_eq!(moved_index(3, 3, 0), 0);
+        // Items it passes over shift by one towards where it came from
+        assert_eq!(moved_index(1, 0, 3), 0);
+        assert_eq!(moved_index(3, 0, 3), 2);
+        assert_eq!(moved_index(0, 3, 0), 1);
+        assert_eq!(moved_index(2, 3, 0), 3);
+        // Items outside the range stay put
+        assert_eq!(moved_index(4, 0, 3), 4);
+        assert_eq!(moved_index(0, 1, 3), 0);
+        assert_eq!(moved_index(5, 5, 5), 5);
+    }
+}
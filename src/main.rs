@@ -18,6 +18,11 @@ use editor::EditorPlugin;
 use playback::PlaybackPlugin;
 use matrix_visuals::{MatrixVisualsPlugin, VisualNoteEvent};
 
+// Debug builds flag any heap allocation made inside the audio callback
+#[cfg(debug_assertions)]
+#[global_allocator]
+static ALLOCATOR: assert_no_alloc::AllocDisabler = assert_no_alloc::AllocDisabler;
+
 fn main() {
     App::new()
         .add_event::<data::ProjectLoadedEvent>()
@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use crate::data::NUM_CHANNELS;
+use crate::data::ProjectData;
 
 pub struct MatrixVisualsPlugin;
 
@@ -31,6 +31,7 @@ pub struct VisualNoteEvent {
 fn spawn_matrix_drops(
     mut commands: Commands,
     mut events: EventReader<VisualNoteEvent>,
+    project: Res<ProjectData>,
 ) {
     // Only show Matrix Rain in View/Play mode (Not Edit)
     // The user said: "while editing show all details", "matrix rain like play"
@@ -46,7 +47,7 @@ fn spawn_matrix_drops(
         // Calculate X position based on Channel (0-7)
         // Screen width approx 1280. 8 Channels.
         // Spread them out.
-        let lane_width = 1280.0 / NUM_CHANNELS as f32;
+        let lane_width = 1280.0 / project.tracks.len().max(1) as f32;
         let x = (event.channel as f32 * lane_width) - (1280.0 / 2.0) + (lane_width / 2.0);
         let y = 360.0; // Top of screen (approx)
 
@@ -1,7 +1,9 @@
-use crate::data::ProjectData;
+use crate::data::{Pattern, ProjectData, TrackConfig, MAX_TRACKS, MAX_WAVETABLES};
+use crate::editor::clamp_cursor;
 use std::fs;
 use std::path::Path;
 
+#[allow(dead_code)]
 pub struct PersistencePlugin;
 
 impl bevy::prelude::Plugin for PersistencePlugin {
@@ -19,6 +21,133 @@ pub fn load_project(path: &str) -> std::io::Result<ProjectData> {
         return Ok(ProjectData::default());
     }
     let data = fs::read_to_string(path)?;
-    let project: ProjectData = serde_json::from_str(&data)?;
+    let mut json: serde_json::Value = serde_json::from_str(&data)?;
+    migrate_track_instruments(&mut json);
+    migrate_aux_buses(&mut json);
+    let mut project: ProjectData = serde_json::from_value(json)?;
+    // Hand-edited files may have patterns outside the 1-256 row range
+    for pattern in project.song_mut().patterns.iter_mut() {
+        pattern.resize(pattern.rows.len());
+    }
+    fit_patterns_to_tracks(&mut project);
+    // Each table is band-limited on the UI thread, so hand-edited files don't get more than the editor allows
+    for instrument in project.instruments.iter_mut() {
+        instrument.wavetables.truncate(MAX_WAVETABLES);
+    }
+    let tracks = project.tracks.len();
+    let song = project.song_mut();
+    if song.patterns.is_empty() {
+        song.patterns.push(Pattern::new(tracks));
+    }
+    if song.order.is_empty() {
+        song.order.push(0);
+    }
+    // The saved cursor may be past the end of what was loaded
+    clamp_cursor(&mut project);
     Ok(project)
 }
+
+/// Every row needs one cell per track. Files from before the track count could change
+/// always have 16 of each; anything else is padded with empty cells or cut to fit.
+fn fit_patterns_to_tracks(project: &mut ProjectData) {
+    project.tracks.truncate(MAX_TRACKS);
+    if project.tracks.is_empty() {
+        project.tracks.push(TrackConfig::default());
+    }
+    let tracks = project.tracks.len();
+    for row in project.song_mut().patterns.iter_mut().flat_map(|p| p.rows.iter_mut()) {
+        row.channels.resize(tracks, Default::default());
+    }
+}
+
+/// Projects saved before the instrument bank picked a waveform with `index % 4`;
+/// the default bank starts with those same four, so point each track at its old one.
+fn migrate_track_instruments(json: &mut serde_json::Value) {
+    if json.get("instruments").is_some() {
+        return;
+    }
+    let Some(tracks) = json.get_mut("tracks").and_then(|t| t.as_array_mut()) else { return };
+    for track in tracks.iter_mut().filter_map(|t| t.as_object_mut()) {
+        let index = track.get("index").and_then(|i| i.as_u64()).unwrap_or(0);
+        track.entry("instrument").or_insert((index % 4).into());
+    }
+}
+
+/// Projects saved before aux buses had one fixed delay and one fixed reverb, with a
+/// send to each on every track. They become buses A and B with the same settings.
+fn migrate_aux_buses(json: &mut serde_json::Value) {
+    let Some(project) = json.as_object_mut() else { return };
+    if project.contains_key("aux_buses") {
+        return;
+    }
+    let mut delay = serde_json::json!({ "name": "DELAY", "effect": "Delay", "return_level": 1.0 });
+    if let Some(settings) = project.remove("delay") {
+        delay["delay"] = settings;
+    }
+    let mut reverb = serde_json::json!({ "name": "REVERB", "effect": "Reverb", "return_level": 1.0 });
+    if let Some(settings) = project.remove("reverb") {
+        reverb["reverb"] = settings;
+    }
+    project.insert("aux_buses".into(), serde_json::json!([delay, reverb]));
+
+    let Some(tracks) = project.get_mut("tracks").and_then(|t| t.as_array_mut()) else { return };
+    for track in tracks.iter_mut().filter_map(|t| t.as_object_mut()) {
+        let delay_send = track.remove("delay_send").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let reverb_send = track.remove("reverb_send").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        track.entry("sends").or_insert(serde_json::json!([delay_send, reverb_send, 0.0, 0.0]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A project as saved when rows were a fixed array of 16 cells: no instrument bank,
+    /// aux buses or order list, and a cursor past the end of its one pattern.
+    fn legacy_project() -> serde_json::Value {
+        let tracks: Vec<_> = (0..16)
+            .map(|i| serde_json::json!({ "name": format!("TRACK {}", i + 1), "index": i, "muted": false, "solo": false, "volume": 1.0 }))
+            .collect();
+        let mut cells = vec![serde_json::json!({ "note": null, "instrument": null, "volume": null, "effect": null, "effect_value": null }); 16];
+        cells[15] = serde_json::json!({ "note": 60, "instrument": 2, "volume": 64, "effect": null, "effect_value": null });
+        let rows = vec![serde_json::json!({ "channels": cells }); 64];
+        serde_json::json!({
+            "bpm": 140,
+            "patterns": [{ "rows": rows }],
+            "current_pattern": 0,
+            "playing": false,
+            "current_row": 200,
+            "current_channel": 15,
+            "speed": 6,
+            "current_octave": 4,
+            "current_instrument": 1,
+            "auto_advance": 1,
+            "tracks": tracks,
+        })
+    }
+
+    #[test]
+    fn legacy_16_track_project_loads() {
+        let path = std::env::temp_dir().join(format!("terminal_velocity_legacy_{}.json", std::process::id()));
+        fs::write(&path, legacy_project().to_string()).unwrap();
+        let project = load_project(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        let project = project.unwrap();
+
+        assert_eq!(project.tracks.len(), 16);
+        assert!(project.patterns()[0].rows.iter().all(|row| row.channels.len() == 16));
+        assert_eq!(project.patterns()[0].rows[0].channels[15].note, Some(60));
+        assert_eq!(project.current_channel, 15);
+        assert_eq!(project.current_row, 63);
+        assert_eq!(project.order(), [0]);
+    }
+
+    #[test]
+    fn a_loaded_project_counts_as_a_new_song_and_track_layout() {
+        let mut project = ProjectData::default();
+        let (song, layout) = (project.song_generation(), project.track_layout);
+        project.replace_with(ProjectData::default());
+        assert_ne!(project.song_generation(), song);
+        assert_ne!(project.track_layout, layout);
+    }
+}
@@ -1,96 +1,262 @@
 use bevy::prelude::*;
-use crate::data::{ProjectData, ROWS_PER_PATTERN};
-use crate::audio_engine::{AudioCommand, AudioEngine};
+use crate::data::{AuxBusSettings, Instrument, InstrumentSettings, MasterSettings, PlayMode, ProjectData, TrackConfig, MAX_AUX_BUSES, MAX_INSTRUMENTS};
+use crate::audio_engine::{AudioCommand, AudioEngine, AudioEvent, SampleData, Song, WavetableData};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use crate::matrix_visuals::VisualNoteEvent;
 
 pub struct PlaybackPlugin;
 
 impl Plugin for PlaybackPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, playback_system);
+        app.add_systems(Update, (sync_track_params, sync_instruments, sync_track_devices, sync_effects, sync_song, playback_system, receive_audio_events).chain());
     }
 }
 
+/// Row timing lives in the audio thread; this system only forwards transport changes.
 fn playback_system(
-    mut project: ResMut<ProjectData>,
-    time: Res<Time>,
-    audio: Res<AudioEngine>, 
-    mut visual_events: EventWriter<VisualNoteEvent>,
-    mut row_timer: Local<f32>,
+    project: Res<ProjectData>,
+    audio: Res<AudioEngine>,
+    mut was_playing: Local<bool>,
     mut last_bpm: Local<u32>,
+    mut last_speed: Local<u32>,
+    mut last_mode: Local<Option<PlayMode>>,
 ) {
     // Send BPM changes to audio engine
     if *last_bpm != project.bpm {
-        let _ = audio.sender.send(crate::audio_engine::AudioCommand::SetBpm(project.bpm as f32));
+        let _ = audio.send(AudioCommand::SetBpm(project.bpm as f32));
         *last_bpm = project.bpm;
     }
-    if !project.playing { 
-        *row_timer = 0.0;
-        return; 
-    }
-
-    let ticks_per_row = project.speed as f32;
-    // Standard Tracker Formula: Time = 2.5 / BPM per tick
-    let row_duration = ticks_per_row * (2.5 / project.bpm as f32);
-
-    *row_timer += time.delta_seconds();
-
-    while *row_timer >= row_duration {
-        *row_timer -= row_duration;
-
-        // 1. Play Current Row
-        let pattern_idx = project.current_pattern;
-        if let Some(pattern) = project.patterns.get(pattern_idx) {
-             if let Some(row) = pattern.rows.get(project.current_row) {
-                 for (ch_idx, cell) in row.channels.iter().enumerate() {
-                     // Check for Note
-                     if let Some(note) = cell.note {
-                         // Check if track is muted
-                         if let Some(track) = project.tracks.get(ch_idx) {
-                             if track.muted {
-                                 continue;
-                             }
-                         }
-                         
-                         // Default params - use track instrument if available
-                         let inst = if let Some(track) = project.tracks.get(ch_idx) {
-                             track.index as u8
-                         } else {
-                             cell.instrument.unwrap_or(project.current_instrument)
-                         };
-                         let vol = cell.volume.unwrap_or(64);
-                         
-                         // Apply track volume
-                         let track_vol = if let Some(track) = project.tracks.get(ch_idx) {
-                             track.volume
-                         } else {
-                             1.0
-                         };
-                         let final_vol = ((vol as f32 / 127.0) * track_vol * 127.0) as u8;
-                         
-                         // Audio Command
-                         let _ = audio.sender.send(AudioCommand::PlayNote {
-                             note,
-                             instrument: inst,
-                             velocity: final_vol.min(127), 
-                             channel: ch_idx,
-                         });
-
-                         // Visual Event
-                         visual_events.send(VisualNoteEvent {
-                             note_name: note_name(note),
-                             channel: ch_idx,
-                         });
-                     }
-                 }
-             }
+    if *last_speed != project.speed {
+        let _ = audio.send(AudioCommand::SetSpeed(project.speed));
+        *last_speed = project.speed;
+    }
+    if *last_mode != Some(project.play_mode) && audio.send(AudioCommand::SetPlayMode(project.play_mode)) {
+        *last_mode = Some(project.play_mode);
+    }
+
+    if project.playing != *was_playing {
+        if project.playing {
+            // Fxx changes come back as events, but one may have been dropped on a full ring
+            let _ = audio.send(AudioCommand::SetBpm(project.bpm as f32));
+            let _ = audio.send(AudioCommand::SetSpeed(project.speed));
         }
-        
-        // 2. Advance Row
-        project.current_row += 1;
-        if project.current_row >= ROWS_PER_PATTERN {
-            project.current_row = 0;
-            // Loop pattern for now
+        let cmd = if project.playing {
+            AudioCommand::Play {
+                position: project.current_order,
+                pattern: project.current_pattern,
+                row: project.current_row,
+            }
+        } else {
+            AudioCommand::Stop
+        };
+        let _ = audio.send(cmd);
+        *was_playing = project.playing;
+    }
+}
+
+/// Publishes mixer values to the audio thread's lock-free parameter block.
+fn sync_track_params(project: Res<ProjectData>, audio: Res<AudioEngine>) {
+    if !project.is_changed() {
+        return;
+    }
+
+    for (track, params) in project.tracks.iter().zip(audio.params.tracks.iter()) {
+        params.set(track.volume, track.muted, track.instrument);
+    }
+}
+
+/// Sends per-track sound settings to the engine when they differ from what it last received.
+fn sync_track_devices(
+    project: Res<ProjectData>,
+    audio: Res<AudioEngine>,
+    mut synced: Local<Vec<Option<TrackConfig>>>,
+    mut synced_layout: Local<u64>,
+) {
+    if !project.is_changed() {
+        return;
+    }
+
+    // After tracks move, the engine's per-track state starts over and every setting is resent
+    if *synced_layout != project.track_layout && audio.send(AudioCommand::ResetTracks) {
+        synced.clear();
+        *synced_layout = project.track_layout;
+    }
+    synced.resize(project.tracks.len(), None);
+    for (i, track) in project.tracks.iter().enumerate() {
+        let last = synced[i].as_ref();
+        let mut delivered = true;
+
+        if last.is_none_or(|t| t.voices != track.voices) {
+            delivered &= audio.send(AudioCommand::SetVoices { track: i, voices: track.voices });
+        }
+        for (bus, &amount) in track.sends.iter().enumerate() {
+            if last.is_none_or(|t| t.sends[bus] != amount) {
+                delivered &= audio.send(AudioCommand::SetSend { track: i, bus, amount });
+            }
+        }
+        if last.is_none_or(|t| t.pan != track.pan) {
+            delivered &= audio.send(AudioCommand::SetPan { track: i, pan: track.pan });
+        }
+
+        // Anything that didn't fit in the command ring is retried next frame
+        if delivered {
+            synced[i] = Some(track.clone());
+        }
+    }
+}
+
+/// Sends instrument definitions to the engine's bank when they change, decoding
+/// sample files as their paths change and rebuilding wavetables as they're edited.
+fn sync_instruments(
+    project: Res<ProjectData>,
+    audio: Res<AudioEngine>,
+    mut synced: Local<Vec<Option<Instrument>>>,
+) {
+    if !project.is_changed() {
+        return;
+    }
+
+    // Slots a smaller bank no longer uses go back to how a fresh engine has them, so stray
+    // instrument numbers in the patterns don't play what an earlier project left there
+    let len = project.instruments.len().min(MAX_INSTRUMENTS);
+    while synced.len() > len {
+        let index = synced.len() - 1;
+        let mut delivered = audio.send(AudioCommand::SetInstrument { index, instrument: InstrumentSettings::default() });
+        delivered &= audio.send(AudioCommand::SetSample { instrument: index, sample: None });
+        delivered &= audio.send(AudioCommand::SetWavetable { instrument: index, wavetable: None });
+        if !delivered {
+            break;
+        }
+        synced.pop();
+    }
+    if synced.len() < len {
+        synced.resize(len, None);
+    }
+    for (i, instrument) in project.instruments.iter().enumerate().take(MAX_INSTRUMENTS) {
+        let last = synced[i].as_ref();
+        let mut delivered = true;
+
+        if last.is_none_or(|inst| inst.settings != instrument.settings) {
+            delivered &= audio.send(AudioCommand::SetInstrument { index: i, instrument: instrument.settings });
+        }
+        if last.is_none_or(|inst| inst.sample_path != instrument.sample_path) {
+            let sample = instrument.sample_path.as_deref().and_then(load_sample);
+            delivered &= audio.send(AudioCommand::SetSample { instrument: i, sample });
+        }
+        if last.is_none_or(|inst| inst.wavetables != instrument.wavetables) {
+            let wavetable = WavetableData::build(&instrument.wavetables).map(Arc::new);
+            delivered &= audio.send(AudioCommand::SetWavetable { instrument: i, wavetable });
+        }
+
+        // Anything that didn't fit in the command ring is retried next frame
+        if delivered {
+            synced[i] = Some(instrument.clone());
+        }
+    }
+}
+
+/// Decodes a sample file. A file that fails to load leaves the instrument silent.
+fn load_sample(path: &str) -> Option<Arc<SampleData>> {
+    match SampleData::load(resolve_sample_path(path)) {
+        Ok(sample) => Some(Arc::new(sample)),
+        Err(e) => {
+            eprintln!("ERROR: Failed to load sample {}: {}", path, e);
+            None
+        },
+    }
+}
+
+/// Relative paths that don't exist from the working directory are looked up next to the
+/// executable and then in the source tree, so the default kit plays wherever it's run from.
+fn resolve_sample_path(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if path.is_absolute() || path.exists() {
+        return path.to_path_buf();
+    }
+    let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    exe_dir.into_iter()
+        .chain([PathBuf::from(env!("CARGO_MANIFEST_DIR"))])
+        .map(|dir| dir.join(path))
+        .find(|candidate| candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Sends the aux buses and master bus when they change. Bus slots the project
+/// doesn't use are switched off.
+fn sync_effects(
+    project: Res<ProjectData>,
+    audio: Res<AudioEngine>,
+    mut synced_buses: Local<[Option<AuxBusSettings>; MAX_AUX_BUSES]>,
+    mut synced_master: Local<Option<MasterSettings>>,
+) {
+    if !project.is_changed() {
+        return;
+    }
+
+    for (bus, synced) in synced_buses.iter_mut().enumerate() {
+        let settings = project.aux_buses.get(bus).map_or(AuxBusSettings::OFF, |b| b.settings);
+        if *synced != Some(settings) && audio.send(AudioCommand::SetAuxBus { bus, settings }) {
+            *synced = Some(settings);
+        }
+    }
+    if *synced_master != Some(project.master) && audio.send(AudioCommand::SetMaster(project.master)) {
+        *synced_master = Some(project.master);
+    }
+}
+
+/// Hands the audio thread a fresh copy of the song whenever its generation moves on.
+fn sync_song(
+    project: Res<ProjectData>,
+    audio: Res<AudioEngine>,
+    mut synced_generation: Local<Option<u64>>,
+) {
+    audio.collect_garbage();
+
+    if *synced_generation == Some(project.song_generation()) {
+        return;
+    }
+
+    let song = Song { patterns: project.patterns().to_vec(), order: project.order().to_vec() };
+    // Only remember what actually made it into the ring, so a full ring retries next frame
+    let command = AudioCommand::LoadSong {
+        song: Box::new(song),
+        position: project.current_order,
+        pattern: project.current_pattern,
+    };
+    if audio.send(command) {
+        *synced_generation = Some(project.song_generation());
+    }
+}
+
+fn receive_audio_events(
+    mut project: ResMut<ProjectData>,
+    audio: Res<AudioEngine>,
+    mut visual_events: EventWriter<VisualNoteEvent>,
+) {
+    while let Some(event) = audio.try_recv_event() {
+        match event {
+            AudioEvent::Position { position, pattern, row } => {
+                // Ignore stale positions that arrive after the user hit stop, and ones from a
+                // song the engine hasn't swapped out yet that no longer fit the patterns
+                let Some(rows) = project.patterns().get(pattern).map(|p| p.rows.len()) else { continue };
+                if project.playing {
+                    project.current_order = position.min(project.order().len().saturating_sub(1));
+                    project.current_pattern = pattern;
+                    project.current_row = row.min(rows.saturating_sub(1));
+                }
+            },
+            AudioEvent::Tempo { bpm, speed } => {
+                // Follow the song so the BPM control shows, and nudges from, what is playing
+                project.bpm = bpm.round() as u32;
+                project.speed = speed;
+            },
+            AudioEvent::NoteOn { channel, note } => {
+                visual_events.send(VisualNoteEvent {
+                    note_name: note_name(note),
+                    channel,
+                });
+            },
         }
     }
 }
@@ -12,7 +12,9 @@ pub enum Theme {
 
 pub struct ThemeColors {
     pub primary: Color32,
+    #[allow(dead_code)]
     pub secondary: Color32,
+    #[allow(dead_code)]
     pub accent: Color32,
     pub background: Color32,
     pub surface: Color32,
@@ -1,11 +1,11 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 use bevy_egui::egui::{Color32, Window};
-use crate::data::{ProjectData, NUM_CHANNELS, ROWS_PER_PATTERN};
-use crate::editor::EditMode;
+use crate::data::{AuxBus, AuxEffect, ChannelData, DelayDivision, DriveMode, DrumKind, DrumSettings, EffectType, ExtendedEffect, FilterMode, FmAlgorithm, Instrument, LfoShape, LoopMode, ModDestination, ModSource, PlayMode, ProjectData, SourceType, VoiceStealing, Waveform, MAX_AUX_BUSES, MAX_BPM, MAX_INSTRUMENTS, MAX_POLYPHONY, MIN_BPM, MAX_PATTERN_ROWS, MAX_TRACKS, MAX_WAVETABLES, NOTE_OFF, FILTER_RESONANCE_BASE, WAVETABLE_SIZE, default_wavetables, effect_text, effect_to_pan, pan_to_effect};
+use crate::editor::{self, EditField, EditMode};
 use crate::persistence::{save_project, load_project};
-use crate::ui_widgets::{knob, cyber_slider};
-use crate::audio_engine::{AudioEngine, AudioCommand};
+use crate::ui_widgets::{knob, cyber_slider, reduction_meter, wave_editor};
+use crate::audio_engine::{cutoff_hz, load_cycle, AudioEngine, AudioCommand};
 use crate::themes::{Theme, ThemeColors};
 use std::collections::VecDeque;
 use rand::Rng;
@@ -29,17 +29,9 @@ struct MatrixColumn {
 #[derive(Default)]
 struct UiState {
     selected_note: Option<(usize, usize)>,
-    device_cutoff: f32,
-    device_resonance: f32,
-    device_drive: f32,
-    device_attack: f32,
-    device_decay: f32,
-    device_sustain: f32,
-    device_release: f32,
-    device_delay: f32,
-    device_reverb: f32,
     playback_start_time: Option<f64>,
     cpu_usage: f32,
+    gain_reduction_db: f32,
     show_help: bool,
     show_about: bool,
     show_settings: bool,
@@ -50,8 +42,13 @@ struct UiState {
     midi_port_selection: String,
     current_theme: Theme,
     matrix_columns: VecDeque<MatrixColumn>,
+    #[allow(dead_code)]
     window_positions: std::collections::HashMap<String, egui::Rect>,
     hover_tooltip: Option<String>,
+    sample_path_input: String,
+    wavetable_index: usize,
+    /// The table being drawn, until the drag ends and it goes back into the instrument
+    wavetable_draft: Option<Vec<f32>>,
 }
 
 #[derive(Clone, Copy, Default)]
@@ -61,10 +58,12 @@ enum FileDialogMode {
     Load,
 }
 
+#[allow(clippy::too_many_arguments)] // Bevy system parameters
 fn ui_system(
     mut contexts: EguiContexts, 
     mut project: ResMut<ProjectData>,
     mut edit_mode: ResMut<EditMode>,
+    edit_field: Res<EditField>,
     mut scroll_y: Local<f32>,
     mut ui_state: Local<UiState>,
     time: Res<Time>,
@@ -92,7 +91,9 @@ fn ui_system(
         ui_state.matrix_columns.clear();
     }
 
-    ui_state.cpu_usage = 2.0 + (time.elapsed_seconds() * 0.1).sin() * 1.5;
+    ui_state.cpu_usage = audio.params.dsp_load.load() * 100.0;
+    // Jump to new reduction, fall back slowly so short peaks stay readable
+    ui_state.gain_reduction_db = audio.params.gain_reduction_db.load().max(ui_state.gain_reduction_db * 0.9);
 
     // File Dialog
     if ui_state.show_file_dialog {
@@ -129,7 +130,7 @@ fn ui_system(
                                 }
                                 FileDialogMode::Load => {
                                     if let Ok(p) = load_project(&filename) {
-                                        *project = p;
+                                        project.replace_with(p);
                                         *edit_mode = EditMode::View;
                                     }
                                 }
@@ -185,7 +186,24 @@ fn ui_system(
                         ui.add_space(5.0);
                         ui.label("Note Entry:");
                         ui.label("  Z S X D C V G B H N J M , : Notes");
+                        ui.label("  1 : Note off");
                         ui.label("  BACKSPACE/DELETE : Delete note");
+                        ui.label("  TAB : Switch note/instrument/effect column");
+                        ui.add_space(5.0);
+                        ui.label("Effects (command + 2 hex digits):");
+                        for effect in EffectType::ALL {
+                            ui.label(format!("  {}xx : {}", effect.command(), effect.name()));
+                        }
+                        for extended in ExtendedEffect::ALL {
+                            ui.label(format!("  E{:X}x : {}", extended.digit(), extended.name()));
+                        }
+                        ui.add_space(5.0);
+                        ui.label("Patterns:");
+                        ui.label("  CTRL+N : New pattern");
+                        ui.label("  CTRL+D : Duplicate pattern");
+                        ui.label("  CTRL+DEL : Delete pattern");
+                        ui.label("  CTRL+← → : Previous / next pattern");
+                        ui.label("  CTRL+SHIFT+← → : Move pattern");
                         ui.add_space(5.0);
                         ui.label("File Operations:");
                         ui.label("  CTRL+S : Save project");
@@ -221,9 +239,35 @@ fn ui_system(
                     
                     ui.label(egui::RichText::new("Note Entry (Edit Mode)").strong().size(11.0));
                     ui.label("Z S X D C V G B H N J M , : Play notes (C to B)");
+                    ui.label("1 : Note off (release)");
                     ui.label("BACKSPACE/DELETE : Delete note");
+                    ui.label("TAB : Switch between the note, instrument and effect column");
+                    ui.add_space(10.0);
+
+                    ui.label(egui::RichText::new("Instrument Entry (Edit Mode, instrument column)").strong().size(11.0));
+                    ui.label("Two hex digits index the instrument bank; BACKSPACE/DELETE clears it");
+                    ui.label("New notes get the instrument selected in the INSTRUMENTS list");
+                    ui.add_space(10.0);
+
+                    ui.label(egui::RichText::new("Effect Entry (Edit Mode, effect column)").strong().size(11.0));
+                    ui.label("Type the command, then two hex digits; BACKSPACE/DELETE clears it");
+                    for effect in EffectType::ALL {
+                        ui.label(format!("{}xx : {}", effect.command(), effect.name()));
+                    }
+                    for extended in ExtendedEffect::ALL {
+                        ui.label(format!("E{:X}x : {}", extended.digit(), extended.name()));
+                    }
+                    ui.label("Tick effects (0, 1, 2, 3, 4, 7, A) run once per tick; SPEED or F01-F1F sets ticks per row");
                     ui.add_space(10.0);
                     
+                    ui.label(egui::RichText::new("Patterns").strong().size(11.0));
+                    ui.label("CTRL+N : New pattern");
+                    ui.label("CTRL+D : Duplicate pattern");
+                    ui.label("CTRL+DEL : Delete pattern (and its order positions)");
+                    ui.label("CTRL+← → : Previous / next pattern");
+                    ui.label("CTRL+SHIFT+← → : Move pattern in the list");
+                    ui.add_space(10.0);
+
                     ui.label(egui::RichText::new("File Operations").strong().size(11.0));
                     ui.label("CTRL+S : Save project");
                     ui.label("CTRL+O : Load project");
@@ -359,23 +403,19 @@ fn ui_system(
                 // BPM Control
                 ui.label(egui::RichText::new("BPM:").size(10.0));
                 let mut bpm_f32 = project.bpm as f32;
-                ui.add(egui::DragValue::new(&mut bpm_f32).speed(1.0).clamp_range(60.0..=200.0));
+                ui.add(egui::DragValue::new(&mut bpm_f32).speed(1.0).clamp_range(MIN_BPM as f32..=MAX_BPM as f32));
                 let new_bpm = bpm_f32 as u32;
                 if new_bpm != project.bpm {
                     project.bpm = new_bpm;
-                    let _ = audio.sender.send(AudioCommand::SetBpm(project.bpm as f32));
+                    let _ = audio.send(AudioCommand::SetBpm(project.bpm as f32));
                 }
-                if ui.small_button("−").clicked() {
-                    if project.bpm > 60 {
-                        project.bpm -= 1;
-                        let _ = audio.sender.send(AudioCommand::SetBpm(project.bpm as f32));
-                    }
+                if ui.small_button("−").clicked() && project.bpm > MIN_BPM {
+                    project.bpm -= 1;
+                    let _ = audio.send(AudioCommand::SetBpm(project.bpm as f32));
                 }
-                if ui.small_button("+").clicked() {
-                    if project.bpm < 200 {
-                        project.bpm += 1;
-                        let _ = audio.sender.send(AudioCommand::SetBpm(project.bpm as f32));
-                    }
+                if ui.small_button("+").clicked() && project.bpm < MAX_BPM {
+                    project.bpm += 1;
+                    let _ = audio.send(AudioCommand::SetBpm(project.bpm as f32));
                 }
                 
                 ui.separator();
@@ -396,6 +436,12 @@ fn ui_system(
                 // Right side: Performance metrics
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     ui.label(egui::RichText::new(format!("CPU: {:.1}%", ui_state.cpu_usage)).size(10.0));
+
+                    // Master bus gain reduction
+                    ui.label(egui::RichText::new(format!("{:.1}dB", -ui_state.gain_reduction_db)).monospace().size(10.0));
+                    reduction_meter(ui, ui_state.gain_reduction_db, 12.0, egui::vec2(50.0, 8.0))
+                        .on_hover_text("Master bus gain reduction (compressor + limiter)");
+                    ui.label(egui::RichText::new("GR:").size(10.0));
                     
                     let playback_time = if let Some(start) = ui_state.playback_start_time {
                         time.elapsed_seconds_f64() - start
@@ -408,6 +454,7 @@ fn ui_system(
                     ui.label(egui::RichText::new(format!("{:02}:{:02}:{:02}", minutes, seconds, frames)).size(10.0));
                     
                     ui.label(egui::RichText::new(format!("R:{:02}", project.current_row)).size(10.0));
+                    ui.label(egui::RichText::new(format!("POS:{:02X} PAT:{:02}", project.current_order, project.current_pattern)).size(10.0));
                 });
             });
         });
@@ -429,7 +476,10 @@ fn ui_system(
                 ui.separator();
                 
                 let current_channel_mixer = project.current_channel;
-                // Show all channels in a single row, no scrolling
+                let instrument_names = instrument_names(&project);
+                let bus_names: Vec<String> = project.aux_buses.iter().map(|bus| bus.name.clone()).collect();
+                let strip_count = project.tracks.len() + project.aux_buses.len();
+                // Show all channels and aux returns in a single row, no scrolling
                 ui.horizontal(|ui| {
                     for (i, track) in project.tracks.iter_mut().enumerate() {
                         let is_selected = i == current_channel_mixer;
@@ -437,7 +487,7 @@ fn ui_system(
                         ui.push_id(i, |ui| {
                             // Calculate width to fit all channels
                             let available_width = ui.available_width();
-                            let channel_width = (available_width / NUM_CHANNELS as f32).max(60.0).min(80.0);
+                            let channel_width = (available_width / strip_count as f32).clamp(60.0, 80.0);
                             
                             let frame = egui::Frame::none()
                                 .fill(if is_selected { colors.active } else { colors.surface })
@@ -476,6 +526,36 @@ fn ui_system(
                                             .size(8.0)
                                             .color(colors.text_dim));
                                     });
+
+                                    ui.add_space(3.0);
+
+                                    // Pan knob
+                                    ui.label(egui::RichText::new("PAN").color(colors.text_dim).size(8.0));
+                                    let pan_knob = knob(ui, &mut track.pan, -1.0..=1.0, 24.0);
+                                    if pan_knob.double_clicked() {
+                                        track.pan = 0.0;
+                                    }
+                                    if pan_knob.hovered() {
+                                        ui_state.hover_tooltip = Some(format!("Channel {} Pan: {}", i + 1, pan_label(track.pan)));
+                                    }
+                                    ui.label(egui::RichText::new(pan_label(track.pan))
+                                        .monospace()
+                                        .size(8.0)
+                                        .color(colors.text_dim));
+
+                                    // Aux sends, one small knob per bus
+                                    if !bus_names.is_empty() {
+                                        ui.add_space(3.0);
+                                        ui.label(egui::RichText::new("SENDS").color(colors.text_dim).size(8.0));
+                                        ui.horizontal_wrapped(|ui| {
+                                            for (bus, name) in bus_names.iter().enumerate() {
+                                                let send = &mut track.sends[bus];
+                                                if knob(ui, send, 0.0..=1.0, 16.0).hovered() {
+                                                    ui_state.hover_tooltip = Some(format!("Channel {} Send {}: {:.0}%", i + 1, name, *send * 100.0));
+                                                }
+                                            }
+                                        });
+                                    }
                                     
                                     ui.add_space(3.0);
                                     
@@ -513,15 +593,12 @@ fn ui_system(
                                     // Instrument selector
                                     ui.add_space(3.0);
                                     ui.label(egui::RichText::new("INST:").color(colors.text_dim).size(8.0));
-                                    let inst_names = ["SINE", "SQUARE", "SAW", "PULSE"];
-                                    let mut inst_idx = (track.index % 4) as usize;
-                                    let inst_label = ui.selectable_label(false, inst_names[inst_idx]);
+                                    let inst_label = ui.selectable_label(false, instrument_label(&instrument_names, track.instrument));
                                     if inst_label.clicked() {
-                                        inst_idx = (inst_idx + 1) % 4;
-                                        track.index = inst_idx;
+                                        track.instrument = next_instrument(&instrument_names, track.instrument);
                                     }
                                     if inst_label.hovered() {
-                                        ui_state.hover_tooltip = Some(format!("Channel {} Instrument: {}", i + 1, inst_names[inst_idx]));
+                                        ui_state.hover_tooltip = Some(format!("Channel {} Instrument: {}", i + 1, instrument_label(&instrument_names, track.instrument)));
                                     }
                                 });
                             });
@@ -529,6 +606,64 @@ fn ui_system(
                         
                         ui.add_space(2.0);
                     }
+
+                    // Aux bus return strips
+                    for (bus, aux) in project.aux_buses.iter_mut().enumerate() {
+                        ui.push_id(("aux_bus", bus), |ui| {
+                            let channel_width = (ui.available_width() / strip_count as f32).clamp(60.0, 80.0);
+                            let frame = egui::Frame::none()
+                                .fill(colors.surface)
+                                .stroke(egui::Stroke::new(1.0, colors.primary))
+                                .inner_margin(egui::Margin::same(4.0));
+
+                            frame.show(ui, |ui| {
+                                ui.set_width(channel_width);
+                                ui.vertical_centered(|ui| {
+                                    ui.label(egui::RichText::new(format!("AUX {}", bus_letter(bus)))
+                                        .monospace()
+                                        .size(9.0)
+                                        .color(colors.primary));
+                                    ui.label(egui::RichText::new(&aux.name)
+                                        .monospace()
+                                        .size(8.0)
+                                        .color(colors.text_dim));
+                                    let settings = &mut aux.settings;
+                                    if ui.selectable_label(false, egui::RichText::new(settings.effect.label()).size(8.0)).clicked() {
+                                        settings.effect = next_aux_effect(settings.effect);
+                                    }
+
+                                    ui.add_space(3.0);
+
+                                    ui.vertical(|ui| {
+                                        ui.label(egui::RichText::new("RTN").color(colors.text_dim).size(8.0));
+                                        let response = ui.add(egui::Slider::new(&mut settings.return_level, 0.0..=1.0)
+                                            .orientation(egui::SliderOrientation::Vertical)
+                                            .show_value(false));
+                                        if response.hovered() {
+                                            ui_state.hover_tooltip = Some(format!("{} Return: {:.0}%", aux.name, settings.return_level * 100.0));
+                                        }
+                                        ui.label(egui::RichText::new(format!("{:.0}%", settings.return_level * 100.0))
+                                            .monospace()
+                                            .size(8.0)
+                                            .color(colors.text_dim));
+                                    });
+
+                                    ui.add_space(3.0);
+
+                                    let mute_color = if settings.muted { Color32::from_rgb(255, 0, 0) } else { colors.text };
+                                    let mute_btn = ui.small_button(egui::RichText::new("M").color(mute_color).size(9.0));
+                                    if mute_btn.clicked() {
+                                        settings.muted = !settings.muted;
+                                    }
+                                    if mute_btn.hovered() {
+                                        ui_state.hover_tooltip = Some(format!("{} Mute", aux.name));
+                                    }
+                                });
+                            });
+                        });
+
+                        ui.add_space(2.0);
+                    }
                 });
             });
     }
@@ -538,12 +673,167 @@ fn ui_system(
         .default_width(260.0)
         .resizable(true)
         .show(ctx, |ui| {
+            // Pattern list: every pattern in the project, in slot order
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new("PATTERNS").size(9.0).color(colors.text_dim));
+            });
+            ui.separator();
+
+            egui::ScrollArea::vertical().id_source("pattern_list").max_height(110.0).show(ui, |ui| {
+                let mut select = None;
+                for (i, pattern) in project.patterns().iter().enumerate() {
+                    let is_current = i == project.current_pattern;
+                    let text = egui::RichText::new(format!("{:02} {}", i, pattern.name))
+                        .monospace()
+                        .size(10.0)
+                        .color(if is_current { colors.primary } else { colors.text });
+                    if ui.selectable_label(is_current, text).clicked() {
+                        select = Some(i);
+                    }
+                }
+                if let Some(i) = select {
+                    editor::select_pattern(&mut project, i);
+                }
+            });
+
+            let current_pattern = project.current_pattern;
+            if let Some(pattern) = project.patterns().get(current_pattern) {
+                let mut name = pattern.name.clone();
+                let mut rows = pattern.rows.len();
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("LEN").monospace().size(8.0));
+                    let response = ui.add(egui::DragValue::new(&mut rows).speed(0.2).clamp_range(1..=MAX_PATTERN_ROWS))
+                        .on_hover_text("Rows in this pattern; rows that still fit keep their notes");
+                    if response.changed() {
+                        editor::resize_pattern(&mut project, current_pattern, rows);
+                    }
+                    let response = ui.add(egui::TextEdit::singleline(&mut name).hint_text("Pattern name").desired_width(f32::INFINITY));
+                    if response.changed() {
+                        editor::rename_pattern(&mut project, current_pattern, &name);
+                    }
+                });
+            }
+
+            ui.horizontal(|ui| {
+                if ui.small_button("NEW").on_hover_text("Add an empty pattern (CTRL+N)").clicked() {
+                    editor::new_pattern(&mut project);
+                }
+                if ui.small_button("DUP").on_hover_text("Duplicate this pattern (CTRL+D)").clicked() {
+                    editor::duplicate_pattern(&mut project, current_pattern);
+                }
+                if ui.small_button("DEL").on_hover_text("Delete this pattern and its order positions (CTRL+DEL)").clicked() {
+                    editor::delete_pattern(&mut project, current_pattern);
+                }
+                if ui.add_enabled(current_pattern > 0, egui::Button::new("▲").small()).clicked() {
+                    editor::move_pattern(&mut project, current_pattern, current_pattern - 1);
+                }
+                if ui.add_enabled(current_pattern + 1 < project.patterns().len(), egui::Button::new("▼").small()).clicked() {
+                    editor::move_pattern(&mut project, current_pattern, current_pattern + 1);
+                }
+            });
+
+            ui.add_space(8.0);
+
+            // Order list: the song as a sequence of patterns
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new("ORDER").size(9.0).color(colors.text_dim));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    for mode in [PlayMode::Pattern, PlayMode::Song] {
+                        let button = ui.selectable_label(project.play_mode == mode, egui::RichText::new(mode.label()).size(9.0));
+                        if button.clicked() {
+                            project.play_mode = mode;
+                        }
+                        if button.hovered() {
+                            ui_state.hover_tooltip = Some(match mode {
+                                PlayMode::Song => "Play through the order list".to_string(),
+                                PlayMode::Pattern => "Loop the current pattern".to_string(),
+                            });
+                        }
+                    }
+                });
+            });
+            ui.separator();
+
+            let pattern_count = project.patterns().len();
+            egui::ScrollArea::vertical().id_source("order_list").max_height(110.0).show(ui, |ui| {
+                let mut select = None;
+                for (position, &pattern) in project.order().iter().enumerate() {
+                    let is_current = position == project.current_order;
+                    let missing = pattern >= pattern_count;
+                    let text = egui::RichText::new(format!("{:02X}  PAT {:02}", position, pattern))
+                        .monospace()
+                        .size(10.0)
+                        .color(if missing { colors.text_dim } else if is_current { colors.primary } else { colors.text });
+                    if ui.selectable_label(is_current, text).clicked() {
+                        select = Some(position);
+                    }
+                }
+                if let Some(position) = select {
+                    project.current_order = position;
+                    if !project.playing && project.order()[position] < pattern_count {
+                        project.current_pattern = project.order()[position];
+                        project.current_row = 0;
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let position = project.current_order.min(project.order().len().saturating_sub(1));
+                if ui.small_button("INS").on_hover_text("Insert the current pattern after this position").clicked() {
+                    let at = (position + 1).min(project.order().len());
+                    let pattern = project.current_pattern;
+                    project.song_mut().order.insert(at, pattern);
+                    project.current_order = at;
+                }
+                if ui.add_enabled(project.order().len() > 1, egui::Button::new("DEL").small()).clicked() {
+                    project.song_mut().order.remove(position);
+                    project.current_order = position.min(project.order().len() - 1);
+                }
+                if ui.small_button("−").on_hover_text("Previous pattern at this position").clicked() {
+                    if let Some(pattern) = project.song_mut().order.get_mut(position) {
+                        *pattern = pattern.saturating_sub(1);
+                    }
+                }
+                if ui.small_button("+").on_hover_text("Next pattern at this position").clicked() {
+                    if let Some(pattern) = project.song_mut().order.get_mut(position) {
+                        *pattern = (*pattern + 1).min(pattern_count.saturating_sub(1));
+                    }
+                }
+                if ui.add_enabled(position > 0, egui::Button::new("▲").small()).clicked() {
+                    project.song_mut().order.swap(position, position - 1);
+                    project.current_order = position - 1;
+                }
+                if ui.add_enabled(position + 1 < project.order().len(), egui::Button::new("▼").small()).clicked() {
+                    project.song_mut().order.swap(position, position + 1);
+                    project.current_order = position + 1;
+                }
+            });
+
+            ui.add_space(8.0);
+
             ui.horizontal(|ui| {
                 ui.label(egui::RichText::new("TRACKS").size(9.0).color(colors.text_dim));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let current = project.current_channel;
+                    let count = project.tracks.len();
+                    if ui.add_enabled(current + 1 < count, egui::Button::new("▼").small()).on_hover_text("Move this track right").clicked() {
+                        editor::move_track(&mut project, current, current + 1);
+                    }
+                    if ui.add_enabled(current > 0, egui::Button::new("▲").small()).on_hover_text("Move this track left").clicked() {
+                        editor::move_track(&mut project, current, current - 1);
+                    }
+                    if ui.add_enabled(count > 1, egui::Button::new("DEL").small()).on_hover_text("Delete this track and its pattern column").clicked() {
+                        editor::remove_track(&mut project, current);
+                    }
+                    if ui.add_enabled(count < MAX_TRACKS, egui::Button::new("ADD").small()).on_hover_text("Add a track after the last one").clicked() {
+                        editor::add_track(&mut project);
+                    }
+                });
             });
             ui.separator();
             
             let current_channel = project.current_channel;
+            let instrument_names = instrument_names(&project);
             egui::ScrollArea::vertical().show(ui, |ui| {
                 for (i, track) in project.tracks.iter_mut().enumerate() {
                     let is_selected = i == current_channel;
@@ -576,11 +866,8 @@ fn ui_system(
                                 
                                 ui.horizontal(|ui| {
                                     ui.label(egui::RichText::new("INST:").color(colors.text_dim).size(9.0));
-                                    let inst_names = ["SINE", "SQUARE", "SAW", "PULSE"];
-                                    let mut inst_idx = (track.index % 4) as usize;
-                                    if ui.selectable_label(false, inst_names[inst_idx]).clicked() {
-                                        inst_idx = (inst_idx + 1) % 4;
-                                        track.index = inst_idx;
+                                    if ui.selectable_label(false, instrument_label(&instrument_names, track.instrument)).clicked() {
+                                        track.instrument = next_instrument(&instrument_names, track.instrument);
                                     }
                                 });
                                 
@@ -634,138 +921,698 @@ fn ui_system(
         .default_width(300.0)
         .resizable(true)
         .show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.label(egui::RichText::new("INSPECTOR").size(9.0).color(colors.text_dim));
-            });
-            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("INSPECTOR").size(9.0).color(colors.text_dim));
+                });
+                ui.separator();
             
-            let frame = egui::Frame::none()
-                .fill(colors.surface)
-                .stroke(egui::Stroke::new(1.0, colors.border))
-                .inner_margin(egui::Margin::same(8.0));
+                let frame = egui::Frame::none()
+                    .fill(colors.surface)
+                    .stroke(egui::Stroke::new(1.0, colors.border))
+                    .inner_margin(egui::Margin::same(8.0));
             
-            frame.show(ui, |ui| {
-                if let Some((row, ch)) = ui_state.selected_note {
-                    if let Some(pattern) = project.patterns.get(project.current_pattern) {
-                        if let Some(row_data) = pattern.rows.get(row) {
-                            if let Some(cell) = row_data.channels.get(ch) {
-                                if let Some(note) = cell.note {
-                                    let note_name_str = note_name(note);
-                                    
-                                    ui.label(egui::RichText::new(format!("NOTE: {}", note_name_str))
-                                        .monospace()
-                                        .color(colors.primary)
-                                        .size(10.0));
-                                    
-                                    ui.add_space(6.0);
-                                    
-                                    ui.label(egui::RichText::new("VELOCITY:").monospace().color(colors.text).size(9.0));
-                                    let velocity = cell.volume.unwrap_or(64);
-                                    let mut vel_f32 = velocity as f32 / 127.0;
-                                    cyber_slider(ui, &mut vel_f32, 0.0..=1.0);
-                                    ui.label(egui::RichText::new(format!("{}", velocity))
-                                        .monospace()
-                                        .size(9.0)
-                                        .color(colors.text_dim));
-                                    
-                                    ui.add_space(6.0);
-                                    
-                                    ui.label(egui::RichText::new("LENGTH: 1/16").monospace().color(colors.text).size(9.0));
-                                    
-                                    ui.add_space(6.0);
-                                    
-                                    ui.label(egui::RichText::new("CC 74:").monospace().color(colors.text).size(9.0));
-                                    let mut cc74 = 64.0 / 127.0;
-                                    knob(ui, &mut cc74, 0.0..=1.0, 40.0);
-                                    ui.label(egui::RichText::new("64").monospace().size(9.0).color(colors.text_dim));
-                                } else {
-                                    ui.label(egui::RichText::new("NOTE: None").monospace().color(colors.text_dim).size(9.0));
+                frame.show(ui, |ui| {
+                    if let Some((row, ch)) = ui_state.selected_note {
+                        let current_pattern = project.current_pattern;
+                        let track_pan = project.tracks.get(ch).map_or(0.0, |t| t.pan);
+                        let track_cc = project.tracks.get(ch)
+                            .and_then(|t| project.instruments.get(t.instrument as usize))
+                            .map_or(127, |inst| (inst.settings.filter.cutoff * 127.0).round() as u8);
+                        // Edits go to a copy, so only a real change counts as a song edit
+                        let cell = project.patterns().get(current_pattern)
+                            .and_then(|pattern| pattern.rows.get(row))
+                            .and_then(|row_data| row_data.channels.get(ch))
+                            .copied();
+                        if let Some(mut cell) = cell {
+                            let mut edited = false;
+                            if let Some(note) = cell.note {
+                                let note_name_str = note_name(note);
+                            
+                                ui.label(egui::RichText::new(format!("NOTE: {}", note_name_str))
+                                    .monospace()
+                                    .color(colors.primary)
+                                    .size(10.0));
+                            
+                                ui.add_space(6.0);
+                            
+                                ui.label(egui::RichText::new("VELOCITY:").monospace().color(colors.text).size(9.0));
+                                let velocity = cell.volume.unwrap_or(64);
+                                let mut vel_f32 = velocity as f32 / 127.0;
+                                cyber_slider(ui, &mut vel_f32, 0.0..=1.0);
+                                ui.label(egui::RichText::new(format!("{}", velocity))
+                                    .monospace()
+                                    .size(9.0)
+                                    .color(colors.text_dim));
+                            
+                                ui.add_space(6.0);
+                            
+                                ui.label(egui::RichText::new("LENGTH: 1/16").monospace().color(colors.text).size(9.0));
+                            
+                                ui.add_space(6.0);
+                            
+                                // CC 74 (cutoff): a Zxx command on this note, otherwise the instrument's filter
+                                ui.label(egui::RichText::new("CC 74:").monospace().color(colors.text).size(9.0));
+                                let cell_cc = cell_filter_cutoff(&cell);
+                                let mut cc74 = cell_cc.unwrap_or(track_cc) as f32 / 127.0;
+                                if knob(ui, &mut cc74, 0.0..=1.0, 40.0).changed() {
+                                    cell.effect = Some(EffectType::FilterControl);
+                                    cell.effect_value = Some((cc74 * 127.0).round() as u8);
+                                    edited = true;
+                                }
+                                let cc_text = match cell_cc {
+                                    Some(cc) => format!("{} (Z{:02X})", cc, cc),
+                                    None => format!("{} (inst)", track_cc),
+                                };
+                                ui.label(egui::RichText::new(cc_text).monospace().size(9.0).color(colors.text_dim));
+
+                                ui.add_space(6.0);
+
+                                // Pan: an 8xx command on this note, otherwise the track's pan
+                                ui.label(egui::RichText::new("PAN:").monospace().color(colors.text).size(9.0));
+                                let cell_pan = cell_pan(&cell);
+                                let mut pan = cell_pan.map_or(track_pan, effect_to_pan);
+                                if knob(ui, &mut pan, -1.0..=1.0, 40.0).changed() {
+                                    cell.effect = Some(EffectType::Panning);
+                                    cell.effect_value = Some(pan_to_effect(pan));
+                                    edited = true;
+                                }
+                                let pan_text = match cell_pan {
+                                    Some(value) => format!("{} (8{:02X})", pan_label(effect_to_pan(value)), value),
+                                    None => format!("{} (track)", pan_label(track_pan)),
+                                };
+                                ui.label(egui::RichText::new(pan_text).monospace().size(9.0).color(colors.text_dim));
+                            } else {
+                                ui.label(egui::RichText::new("NOTE: None").monospace().color(colors.text_dim).size(9.0));
+                            }
+                            if edited {
+                                if let Some(target) = project.cell_mut(current_pattern, row, ch) {
+                                    *target = cell;
                                 }
                             }
                         }
+                    } else {
+                        ui.label(egui::RichText::new("NOTE: None").monospace().color(colors.text_dim).size(9.0));
                     }
-                } else {
-                    ui.label(egui::RichText::new("NOTE: None").monospace().color(colors.text_dim).size(9.0));
-                }
-            });
+                });
             
-            ui.add_space(15.0);
+                ui.add_space(15.0);
             
-            ui.horizontal(|ui| {
-                ui.label(egui::RichText::new("DEVICE").size(9.0).color(colors.text_dim));
-            });
-            ui.separator();
+                let device_ch = project.current_channel;
+                let device_inst = project.tracks.get(device_ch).map_or(0, |t| t.instrument as usize);
+
+                // Instrument bank: clicking an entry assigns it to the current track
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("INSTRUMENTS").size(9.0).color(colors.text_dim));
+                    let can_add = project.instruments.len() < MAX_INSTRUMENTS;
+                    if ui.add_enabled(can_add, egui::Button::new("NEW")).clicked() {
+                        let name = format!("INST {:02X}", project.instruments.len());
+                        project.instruments.push(Instrument::oscillator(&name, Waveform::Sine));
+                    }
+                    if ui.add_enabled(can_add && device_inst < project.instruments.len(), egui::Button::new("DUP")).clicked() {
+                        let copy = project.instruments[device_inst].clone();
+                        project.instruments.push(copy);
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical().id_source("instrument_list").max_height(120.0).show(ui, |ui| {
+                    let mut assign = None;
+                    for (i, inst) in project.instruments.iter_mut().enumerate() {
+                        if i == device_inst {
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new(format!("{:02X}", i)).monospace().size(9.0).color(colors.primary));
+                                ui.text_edit_singleline(&mut inst.name);
+                            });
+                        } else if ui.selectable_label(false, egui::RichText::new(format!("{:02X} {}", i, inst.name)).monospace().size(9.0)).clicked() {
+                            assign = Some(i as u8);
+                        }
+                    }
+                    if let Some(i) = assign {
+                        if let Some(track) = project.tracks.get_mut(device_ch) {
+                            track.instrument = i;
+                        }
+                        project.current_instrument = i;
+                    }
+                });
+
+                ui.add_space(15.0);
+
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("DEVICE").size(9.0).color(colors.text_dim));
+                    if let Some(track) = project.tracks.get(device_ch) {
+                        ui.label(egui::RichText::new(format!("CH{:02} {}", device_ch + 1, track.name))
+                            .monospace()
+                            .size(9.0)
+                            .color(colors.primary));
+                    }
+                    if let Some(inst) = project.instruments.get(device_inst) {
+                        ui.label(egui::RichText::new(format!("INS {:02X} {}", device_inst, inst.name))
+                            .monospace()
+                            .size(9.0)
+                            .color(colors.text));
+                    }
+                });
+                ui.separator();
             
-            let frame = egui::Frame::none()
-                .fill(colors.surface)
-                .stroke(egui::Stroke::new(1.0, colors.border))
-                .inner_margin(egui::Margin::same(8.0));
+                let frame = egui::Frame::none()
+                    .fill(colors.surface)
+                    .stroke(egui::Stroke::new(1.0, colors.border))
+                    .inner_margin(egui::Margin::same(8.0));
             
-            frame.show(ui, |ui| {
-                ui.vertical(|ui| {
-                    // Filter Section
-                    ui.label(egui::RichText::new("FILTER").size(9.0).color(colors.text_dim));
-                    ui.horizontal(|ui| {
-                        ui.vertical(|ui| {
-                            knob(ui, &mut ui_state.device_cutoff, 0.0..=1.0, 45.0);
-                            ui.label(egui::RichText::new("CUTOFF").monospace().size(8.0).color(colors.text));
-                        });
+                frame.show(ui, |ui| {
+                    ui.vertical(|ui| {
+                        // Instrument source
+                        if let Some(inst) = project.instruments.get_mut(device_inst) {
+                            let settings = &mut inst.settings;
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new("SOURCE").size(9.0).color(colors.text_dim));
+                                if ui.selectable_label(false, settings.source.label()).clicked() {
+                                    let idx = SourceType::ALL.iter().position(|m| *m == settings.source).unwrap_or(0);
+                                    settings.source = SourceType::ALL[(idx + 1) % SourceType::ALL.len()];
+                                    // Give a fresh wavetable instrument something to draw over
+                                    if settings.source == SourceType::Wavetable && inst.wavetables.is_empty() {
+                                        inst.wavetables = default_wavetables();
+                                    }
+                                }
+                            });
+                            ui.add_space(4.0);
+
+                            match settings.source {
+                                SourceType::Oscillator => {
+                                    let osc = &mut settings.oscillator;
+                                    ui.horizontal(|ui| {
+                                        ui.label(egui::RichText::new("OSCILLATOR").size(9.0).color(colors.text_dim));
+                                        if ui.selectable_label(false, osc.waveform.label()).clicked() {
+                                            let idx = Waveform::ALL.iter().position(|m| *m == osc.waveform).unwrap_or(0);
+                                            osc.waveform = Waveform::ALL[(idx + 1) % Waveform::ALL.len()];
+                                        }
+                                    });
+                                    ui.vertical(|ui| {
+                                        knob(ui, &mut osc.pulse_width, 0.05..=0.95, 45.0);
+                                        ui.label(egui::RichText::new("PW").monospace().size(8.0).color(colors.text));
+                                        ui.label(egui::RichText::new(format!("{:.0}%", osc.pulse_width * 100.0)).monospace().size(8.0).color(colors.text_dim));
+                                    });
+                                },
+                                SourceType::Fm => {
+                                    let fm = &mut settings.fm;
+                                    ui.horizontal(|ui| {
+                                        ui.label(egui::RichText::new("FM ALGO").size(9.0).color(colors.text_dim));
+                                        if ui.selectable_label(false, fm.algorithm.label()).clicked() {
+                                            let idx = FmAlgorithm::ALL.iter().position(|m| *m == fm.algorithm).unwrap_or(0);
+                                            fm.algorithm = FmAlgorithm::ALL[(idx + 1) % FmAlgorithm::ALL.len()];
+                                        }
+                                    });
+                                    ui.vertical(|ui| {
+                                        knob(ui, &mut fm.feedback, 0.0..=1.0, 30.0);
+                                        ui.label(egui::RichText::new("FDBK").monospace().size(8.0).color(colors.text));
+                                    });
+                                    let carriers = fm.algorithm.carriers();
+                                    for (i, op) in fm.operators.iter_mut().enumerate().take(fm.algorithm.operator_count()) {
+                                        ui.push_id(("fm_op", i), |ui| {
+                                            // Carriers set output level, modulators set modulation index
+                                            let role = if carriers & (1 << i) != 0 { "LVL" } else { "IDX" };
+                                            ui.horizontal(|ui| {
+                                                ui.label(egui::RichText::new(format!("OP{}", i + 1)).monospace().size(9.0).color(colors.primary));
+                                                ui.label(egui::RichText::new("RATIO").monospace().size(8.0).color(colors.text));
+                                                ui.add(egui::DragValue::new(&mut op.ratio).speed(0.01).clamp_range(0.0..=16.0).max_decimals(2));
+                                            });
+                                            ui.horizontal(|ui| {
+                                                for (value, range, label) in [
+                                                    (&mut op.level, 0.0..=1.0, role),
+                                                    (&mut op.envelope.attack, 0.0..=2.0, "A"),
+                                                    (&mut op.envelope.decay, 0.0..=4.0, "D"),
+                                                    (&mut op.envelope.sustain, 0.0..=1.0, "S"),
+                                                    (&mut op.envelope.release, 0.0..=4.0, "R"),
+                                                ] {
+                                                    ui.vertical(|ui| {
+                                                        knob(ui, value, range, 26.0);
+                                                        ui.label(egui::RichText::new(label).monospace().size(8.0).color(colors.text));
+                                                    });
+                                                }
+                                            });
+                                        });
+                                    }
+                                },
+                                SourceType::Wavetable => {
+                                    let count = inst.wavetables.len();
+                                    ui_state.wavetable_index = ui_state.wavetable_index.min(count.saturating_sub(1));
+                                    ui.horizontal(|ui| {
+                                        ui.label(egui::RichText::new("TABLE").size(9.0).color(colors.text_dim));
+                                        if ui.small_button("<").clicked() {
+                                            ui_state.wavetable_index = ui_state.wavetable_index.saturating_sub(1);
+                                        }
+                                        ui.label(egui::RichText::new(format!("{}/{}", ui_state.wavetable_index + 1, count)).monospace().size(9.0).color(colors.primary));
+                                        if ui.small_button(">").clicked() && ui_state.wavetable_index + 1 < count {
+                                            ui_state.wavetable_index += 1;
+                                        }
+                                        if ui.add_enabled(count < MAX_WAVETABLES, egui::Button::new("ADD")).clicked() {
+                                            let copy = inst.wavetables.get(ui_state.wavetable_index).cloned().unwrap_or_else(|| vec![0.0; WAVETABLE_SIZE]);
+                                            inst.wavetables.insert((ui_state.wavetable_index + 1).min(count), copy);
+                                            ui_state.wavetable_index = (ui_state.wavetable_index + 1).min(count);
+                                        }
+                                        if ui.add_enabled(count > 1, egui::Button::new("DEL")).clicked() {
+                                            inst.wavetables.remove(ui_state.wavetable_index);
+                                            ui_state.wavetable_index = ui_state.wavetable_index.saturating_sub(1);
+                                        }
+                                    });
+                                    // Drawing edits a draft, so the tables are only band-limited again once the drag ends
+                                    if let Some(table) = inst.wavetables.get_mut(ui_state.wavetable_index) {
+                                        let mut draft = ui_state.wavetable_draft.take().unwrap_or_else(|| table.clone());
+                                        if wave_editor(ui, &mut draft, egui::vec2(220.0, 70.0)).dragged() {
+                                            ui_state.wavetable_draft = Some(draft);
+                                        } else if draft != *table {
+                                            *table = draft;
+                                        }
+                                    }
+                                    ui.horizontal(|ui| {
+                                        ui.add(egui::TextEdit::singleline(&mut ui_state.sample_path_input).hint_text("cycle.wav / .flac").desired_width(110.0));
+                                        if ui.button("IMPORT").clicked() && !ui_state.sample_path_input.is_empty() {
+                                            match load_cycle(&ui_state.sample_path_input) {
+                                                Ok(cycle) => match inst.wavetables.get_mut(ui_state.wavetable_index) {
+                                                    Some(table) => *table = cycle,
+                                                    None => inst.wavetables.push(cycle),
+                                                },
+                                                Err(e) => eprintln!("ERROR: Failed to import {}: {}", ui_state.sample_path_input, e),
+                                            }
+                                        }
+                                    });
+                                    let wavetable = &mut inst.settings.wavetable;
+                                    ui.vertical(|ui| {
+                                        knob(ui, &mut wavetable.position, 0.0..=1.0, 45.0);
+                                        ui.label(egui::RichText::new("POS").monospace().size(8.0).color(colors.text));
+                                        ui.label(egui::RichText::new(format!("{:.0}%", wavetable.position * 100.0)).monospace().size(8.0).color(colors.text_dim));
+                                    });
+                                },
+                                SourceType::Drum => {
+                                    let drum = &mut settings.drum;
+                                    ui.horizontal(|ui| {
+                                        ui.label(egui::RichText::new("DRUM").size(9.0).color(colors.text_dim));
+                                        // A new kind starts from its own preset, keeping the tuning
+                                        if ui.selectable_label(false, drum.kind.label()).clicked() {
+                                            let idx = DrumKind::ALL.iter().position(|m| *m == drum.kind).unwrap_or(0);
+                                            let kind = DrumKind::ALL[(idx + 1) % DrumKind::ALL.len()];
+                                            *drum = DrumSettings { tune: drum.tune, ..DrumSettings::new(kind) };
+                                        }
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.vertical(|ui| {
+                                            knob(ui, &mut drum.tune, -24.0..=24.0, 35.0);
+                                            ui.label(egui::RichText::new("TUNE").monospace().size(8.0).color(colors.text));
+                                            ui.label(egui::RichText::new(format!("{:+.1}", drum.tune)).monospace().size(8.0).color(colors.text_dim));
+                                        });
+                                        ui.add_space(4.0);
+                                        ui.vertical(|ui| {
+                                            knob(ui, &mut drum.decay, 0.01..=2.0, 35.0);
+                                            ui.label(egui::RichText::new("DECAY").monospace().size(8.0).color(colors.text));
+                                            ui.label(egui::RichText::new(format!("{:.2}s", drum.decay)).monospace().size(8.0).color(colors.text_dim));
+                                        });
+                                        ui.add_space(4.0);
+                                        ui.vertical(|ui| {
+                                            knob(ui, &mut drum.sweep, 0.0..=1.0, 35.0);
+                                            ui.label(egui::RichText::new("SWEEP").monospace().size(8.0).color(colors.text));
+                                            ui.label(egui::RichText::new(format!("{:.0}%", drum.sweep * 100.0)).monospace().size(8.0).color(colors.text_dim));
+                                        });
+                                        ui.add_space(4.0);
+                                        ui.vertical(|ui| {
+                                            knob(ui, &mut drum.tone, 0.0..=1.0, 35.0);
+                                            ui.label(egui::RichText::new("TONE").monospace().size(8.0).color(colors.text));
+                                            ui.label(egui::RichText::new(format!("{:.0}%", drum.tone * 100.0)).monospace().size(8.0).color(colors.text_dim));
+                                        });
+                                    });
+                                },
+                                SourceType::Sampler => {
+                                    ui.label(egui::RichText::new("SAMPLE").size(9.0).color(colors.text_dim));
+                                    let loaded = inst.sample_path.as_deref()
+                                        .map(|path| path.rsplit(['/', '\\']).next().unwrap_or(path))
+                                        .unwrap_or("-- none --");
+                                    ui.label(egui::RichText::new(loaded).monospace().size(8.0).color(colors.primary));
+                                    ui.horizontal(|ui| {
+                                        ui.add(egui::TextEdit::singleline(&mut ui_state.sample_path_input).hint_text("path.wav / .flac").desired_width(110.0));
+                                        if ui.button("LOAD").clicked() && !ui_state.sample_path_input.is_empty() {
+                                            inst.sample_path = Some(ui_state.sample_path_input.clone());
+                                        }
+                                        if ui.button("CLEAR").clicked() {
+                                            inst.sample_path = None;
+                                        }
+                                    });
+
+                                    let sampler = &mut inst.settings.sampler;
+                                    ui.horizontal(|ui| {
+                                        ui.label(egui::RichText::new("ROOT").monospace().size(8.0).color(colors.text));
+                                        ui.add(egui::DragValue::new(&mut sampler.root_note).speed(0.2).clamp_range(0..=119)
+                                            .custom_formatter(|n, _| note_name(n as u8)));
+                                        if ui.selectable_label(false, sampler.loop_mode.label()).clicked() {
+                                            let idx = LoopMode::ALL.iter().position(|m| *m == sampler.loop_mode).unwrap_or(0);
+                                            sampler.loop_mode = LoopMode::ALL[(idx + 1) % LoopMode::ALL.len()];
+                                        }
+                                        if ui.selectable_label(sampler.one_shot, "ONE-SHOT").clicked() {
+                                            sampler.one_shot = !sampler.one_shot;
+                                        }
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.vertical(|ui| {
+                                            knob(ui, &mut sampler.fine_tune, -100.0..=100.0, 35.0);
+                                            ui.label(egui::RichText::new("FINE").monospace().size(8.0).color(colors.text));
+                                            ui.label(egui::RichText::new(format!("{:+.0}c", sampler.fine_tune)).monospace().size(8.0).color(colors.text_dim));
+                                        });
+                                        ui.add_space(4.0);
+                                        ui.vertical(|ui| {
+                                            ui.label(egui::RichText::new("LOOP START").monospace().size(8.0).color(colors.text));
+                                            ui.add(egui::DragValue::new(&mut sampler.loop_start).speed(10.0));
+                                            ui.label(egui::RichText::new("LOOP END (0 = END)").monospace().size(8.0).color(colors.text));
+                                            ui.add(egui::DragValue::new(&mut sampler.loop_end).speed(10.0));
+                                        });
+                                    });
+                                },
+                            }
+                        }
+
                         ui.add_space(8.0);
-                        ui.vertical(|ui| {
-                            knob(ui, &mut ui_state.device_resonance, 0.0..=1.0, 45.0);
-                            ui.label(egui::RichText::new("RES").monospace().size(8.0).color(colors.text));
-                        });
-                    });
+
+                        // Filter Section
+                        if let Some(inst) = project.instruments.get_mut(device_inst) {
+                            let filter = &mut inst.settings.filter;
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new("FILTER").size(9.0).color(colors.text_dim));
+                                if ui.selectable_label(false, filter.mode.label()).clicked() {
+                                    let idx = FilterMode::ALL.iter().position(|m| *m == filter.mode).unwrap_or(0);
+                                    filter.mode = FilterMode::ALL[(idx + 1) % FilterMode::ALL.len()];
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut filter.cutoff, 0.0..=1.0, 45.0);
+                                    ui.label(egui::RichText::new("CUTOFF").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.0}Hz", cutoff_hz(filter.cutoff))).monospace().size(8.0).color(colors.text_dim));
+                                });
+                                ui.add_space(8.0);
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut filter.resonance, 0.0..=1.0, 45.0);
+                                    ui.label(egui::RichText::new("RES").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.0}%", filter.resonance * 100.0)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                            });
+                        }
                     
-                    ui.add_space(8.0);
+                        ui.add_space(8.0);
                     
-                    // Distortion
-                    ui.label(egui::RichText::new("DISTORTION").size(9.0).color(colors.text_dim));
-                    ui.vertical(|ui| {
-                        knob(ui, &mut ui_state.device_drive, 0.0..=1.0, 45.0);
-                        ui.label(egui::RichText::new("DRIVE").monospace().size(8.0).color(colors.text));
-                    });
+                        // Distortion
+                        if let Some(inst) = project.instruments.get_mut(device_inst) {
+                            let drive = &mut inst.settings.drive;
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new("DISTORTION").size(9.0).color(colors.text_dim));
+                                if ui.selectable_label(false, drive.mode.label()).clicked() {
+                                    let idx = DriveMode::ALL.iter().position(|m| *m == drive.mode).unwrap_or(0);
+                                    drive.mode = DriveMode::ALL[(idx + 1) % DriveMode::ALL.len()];
+                                }
+                            });
+                            ui.vertical(|ui| {
+                                knob(ui, &mut drive.drive, 0.0..=1.0, 45.0);
+                                ui.label(egui::RichText::new("DRIVE").monospace().size(8.0).color(colors.text));
+                                ui.label(egui::RichText::new(format!("{:.0}%", drive.drive * 100.0)).monospace().size(8.0).color(colors.text_dim));
+                            });
+                        }
                     
-                    ui.add_space(8.0);
+                        ui.add_space(8.0);
                     
-                    // ADSR Envelope
-                    ui.label(egui::RichText::new("ENVELOPE").size(9.0).color(colors.text_dim));
-                    ui.horizontal(|ui| {
-                        ui.vertical(|ui| {
-                            knob(ui, &mut ui_state.device_attack, 0.0..=1.0, 35.0);
-                            ui.label(egui::RichText::new("A").monospace().size(8.0).color(colors.text));
-                        });
-                        ui.add_space(4.0);
-                        ui.vertical(|ui| {
-                            knob(ui, &mut ui_state.device_decay, 0.0..=1.0, 35.0);
-                            ui.label(egui::RichText::new("D").monospace().size(8.0).color(colors.text));
-                        });
-                        ui.add_space(4.0);
-                        ui.vertical(|ui| {
-                            knob(ui, &mut ui_state.device_sustain, 0.0..=1.0, 35.0);
-                            ui.label(egui::RichText::new("S").monospace().size(8.0).color(colors.text));
-                        });
-                        ui.add_space(4.0);
-                        ui.vertical(|ui| {
-                            knob(ui, &mut ui_state.device_release, 0.0..=1.0, 35.0);
-                            ui.label(egui::RichText::new("R").monospace().size(8.0).color(colors.text));
-                        });
-                    });
+                        // ADSR Envelope (per instrument, synced to the engine by playback::sync_instruments)
+                        ui.label(egui::RichText::new("ENVELOPE").size(9.0).color(colors.text_dim));
+                        if let Some(inst) = project.instruments.get_mut(device_inst) {
+                            let env = &mut inst.settings.envelope;
+                            ui.horizontal(|ui| {
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut env.attack, 0.0..=2.0, 35.0);
+                                    ui.label(egui::RichText::new("A").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.2}s", env.attack)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                                ui.add_space(4.0);
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut env.decay, 0.0..=4.0, 35.0);
+                                    ui.label(egui::RichText::new("D").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.2}s", env.decay)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                                ui.add_space(4.0);
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut env.sustain, 0.0..=1.0, 35.0);
+                                    ui.label(egui::RichText::new("S").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.0}%", env.sustain * 100.0)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                                ui.add_space(4.0);
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut env.release, 0.0..=4.0, 35.0);
+                                    ui.label(egui::RichText::new("R").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.2}s", env.release)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                            });
+                        }
+
+                        ui.add_space(8.0);
+
+                        // Modulation: two LFOs, a spare envelope and the routing matrix
+                        ui.label(egui::RichText::new("MODULATION").size(9.0).color(colors.text_dim));
+                        if let Some(inst) = project.instruments.get_mut(device_inst) {
+                            let modulation = &mut inst.settings.modulation;
+                            for (i, lfo) in modulation.lfos.iter_mut().enumerate() {
+                                ui.push_id(("lfo", i), |ui| {
+                                    ui.horizontal(|ui| {
+                                        ui.label(egui::RichText::new(format!("LFO{}", i + 1)).monospace().size(9.0).color(colors.primary));
+                                        if ui.selectable_label(false, lfo.shape.label()).clicked() {
+                                            let idx = LfoShape::ALL.iter().position(|m| *m == lfo.shape).unwrap_or(0);
+                                            lfo.shape = LfoShape::ALL[(idx + 1) % LfoShape::ALL.len()];
+                                        }
+                                        if lfo.sync {
+                                            if ui.selectable_label(false, lfo.division.label()).clicked() {
+                                                let idx = DelayDivision::ALL.iter().position(|d| *d == lfo.division).unwrap_or(0);
+                                                lfo.division = DelayDivision::ALL[(idx + 1) % DelayDivision::ALL.len()];
+                                            }
+                                        } else {
+                                            ui.add(egui::DragValue::new(&mut lfo.rate).speed(0.05).clamp_range(0.01..=40.0).max_decimals(2).suffix("Hz"));
+                                        }
+                                        if ui.selectable_label(lfo.sync, "SYNC").clicked() {
+                                            lfo.sync = !lfo.sync;
+                                        }
+                                        if ui.selectable_label(lfo.retrigger, "RETRIG").clicked() {
+                                            lfo.retrigger = !lfo.retrigger;
+                                        }
+                                    });
+                                });
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new("MOD ENV").monospace().size(9.0).color(colors.primary));
+                                let env = &mut modulation.envelope;
+                                for (value, range, label) in [
+                                    (&mut env.attack, 0.0..=2.0, "A"),
+                                    (&mut env.decay, 0.0..=4.0, "D"),
+                                    (&mut env.sustain, 0.0..=1.0, "S"),
+                                    (&mut env.release, 0.0..=4.0, "R"),
+                                ] {
+                                    ui.vertical(|ui| {
+                                        knob(ui, value, range, 26.0);
+                                        ui.label(egui::RichText::new(label).monospace().size(8.0).color(colors.text));
+                                    });
+                                }
+                            });
+                            for (i, route) in modulation.routes.iter_mut().enumerate() {
+                                ui.push_id(("mod_route", i), |ui| {
+                                    ui.horizontal(|ui| {
+                                        if ui.selectable_label(false, route.source.label()).clicked() {
+                                            let idx = ModSource::ALL.iter().position(|m| *m == route.source).unwrap_or(0);
+                                            route.source = ModSource::ALL[(idx + 1) % ModSource::ALL.len()];
+                                        }
+                                        ui.label(egui::RichText::new(">").monospace().size(9.0).color(colors.text_dim));
+                                        if ui.selectable_label(false, route.destination.label()).clicked() {
+                                            let idx = ModDestination::ALL.iter().position(|m| *m == route.destination).unwrap_or(0);
+                                            route.destination = ModDestination::ALL[(idx + 1) % ModDestination::ALL.len()];
+                                        }
+                                        ui.add(egui::DragValue::new(&mut route.amount).speed(0.01).clamp_range(-1.0..=1.0).max_decimals(2));
+                                    });
+                                });
+                            }
+                        }
+
+                        ui.add_space(8.0);
+
+                        // Voices (per track)
+                        if let Some(track) = project.tracks.get_mut(device_ch) {
+                            let voices = &mut track.voices;
+                            ui.horizontal(|ui| {
+                                ui.label(egui::RichText::new("VOICES").size(9.0).color(colors.text_dim));
+                                ui.add(egui::DragValue::new(&mut voices.polyphony).speed(0.1).clamp_range(1..=MAX_POLYPHONY));
+                                if ui.selectable_label(false, voices.stealing.label()).clicked() {
+                                    let idx = VoiceStealing::ALL.iter().position(|m| *m == voices.stealing).unwrap_or(0);
+                                    voices.stealing = VoiceStealing::ALL[(idx + 1) % VoiceStealing::ALL.len()];
+                                }
+                            });
+                        }
                     
-                    ui.add_space(8.0);
+                        ui.add_space(8.0);
                     
-                    // Effects
-                    ui.label(egui::RichText::new("EFFECTS").size(9.0).color(colors.text_dim));
-                    ui.horizontal(|ui| {
-                        ui.vertical(|ui| {
-                            knob(ui, &mut ui_state.device_delay, 0.0..=1.0, 40.0);
-                            ui.label(egui::RichText::new("DELAY").monospace().size(8.0).color(colors.text));
+                        // Aux sends (per track)
+                        ui.label(egui::RichText::new("SENDS").size(9.0).color(colors.text_dim));
+                        ui.horizontal(|ui| {
+                            let names: Vec<String> = project.aux_buses.iter().map(|bus| bus.name.clone()).collect();
+                            if let Some(track) = project.tracks.get_mut(device_ch) {
+                                for (bus, name) in names.iter().enumerate() {
+                                    ui.vertical(|ui| {
+                                        knob(ui, &mut track.sends[bus], 0.0..=1.0, 40.0);
+                                        ui.label(egui::RichText::new(name).monospace().size(8.0).color(colors.text));
+                                        ui.label(egui::RichText::new(format!("{:.0}%", track.sends[bus] * 100.0)).monospace().size(8.0).color(colors.text_dim));
+                                    });
+                                    ui.add_space(8.0);
+                                }
+                            }
                         });
+
                         ui.add_space(8.0);
-                        ui.vertical(|ui| {
-                            knob(ui, &mut ui_state.device_reverb, 0.0..=1.0, 40.0);
-                            ui.label(egui::RichText::new("REVERB").monospace().size(8.0).color(colors.text));
+
+                        // Aux buses (project-wide): each hosts a delay or a reverb
+                        let mut removed_bus = None;
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new("AUX BUSES").size(9.0).color(colors.text_dim));
+                            if project.aux_buses.len() < MAX_AUX_BUSES && ui.small_button("ADD").clicked() {
+                                let name = format!("AUX {}", bus_letter(project.aux_buses.len()));
+                                project.aux_buses.push(AuxBus::new(&name, AuxEffect::Delay));
+                            }
                         });
+                        for (bus, aux) in project.aux_buses.iter_mut().enumerate() {
+                            ui.push_id(("aux_settings", bus), |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label(egui::RichText::new(bus_letter(bus).to_string()).monospace().size(9.0).color(colors.primary));
+                                    ui.add(egui::TextEdit::singleline(&mut aux.name).desired_width(70.0));
+                                    let settings = &mut aux.settings;
+                                    if ui.selectable_label(false, settings.effect.label()).clicked() {
+                                        settings.effect = next_aux_effect(settings.effect);
+                                    }
+                                    if ui.small_button("DEL").clicked() {
+                                        removed_bus = Some(bus);
+                                    }
+                                });
+
+                                let settings = &mut aux.settings;
+                                match settings.effect {
+                                    AuxEffect::Delay => {
+                                        let delay = &mut settings.delay;
+                                        ui.horizontal(|ui| {
+                                            if ui.selectable_label(false, delay.division.label()).clicked() {
+                                                let idx = DelayDivision::ALL.iter().position(|d| *d == delay.division).unwrap_or(0);
+                                                delay.division = DelayDivision::ALL[(idx + 1) % DelayDivision::ALL.len()];
+                                            }
+                                            if ui.selectable_label(delay.ping_pong, "PING-PONG").clicked() {
+                                                delay.ping_pong = !delay.ping_pong;
+                                            }
+                                        });
+                                        ui.horizontal(|ui| {
+                                            ui.vertical(|ui| {
+                                                knob(ui, &mut delay.feedback, 0.0..=0.95, 30.0);
+                                                ui.label(egui::RichText::new("FDBK").monospace().size(8.0).color(colors.text));
+                                            });
+                                            ui.add_space(4.0);
+                                            ui.vertical(|ui| {
+                                                knob(ui, &mut delay.damping, 0.0..=1.0, 30.0);
+                                                ui.label(egui::RichText::new("DAMP").monospace().size(8.0).color(colors.text));
+                                            });
+                                            ui.add_space(4.0);
+                                            ui.vertical(|ui| {
+                                                knob(ui, &mut settings.return_level, 0.0..=1.0, 30.0);
+                                                ui.label(egui::RichText::new("RTN").monospace().size(8.0).color(colors.text));
+                                            });
+                                        });
+                                    },
+                                    AuxEffect::Reverb => {
+                                        let reverb = &mut settings.reverb;
+                                        ui.horizontal(|ui| {
+                                            ui.vertical(|ui| {
+                                                knob(ui, &mut reverb.size, 0.0..=1.0, 30.0);
+                                                ui.label(egui::RichText::new("SIZE").monospace().size(8.0).color(colors.text));
+                                            });
+                                            ui.add_space(4.0);
+                                            ui.vertical(|ui| {
+                                                knob(ui, &mut reverb.damping, 0.0..=1.0, 30.0);
+                                                ui.label(egui::RichText::new("DAMP").monospace().size(8.0).color(colors.text));
+                                            });
+                                            ui.add_space(4.0);
+                                            ui.vertical(|ui| {
+                                                knob(ui, &mut reverb.wet, 0.0..=1.0, 30.0);
+                                                ui.label(egui::RichText::new("WET").monospace().size(8.0).color(colors.text));
+                                            });
+                                            ui.add_space(4.0);
+                                            ui.vertical(|ui| {
+                                                knob(ui, &mut reverb.pre_delay_ms, 0.0..=200.0, 30.0);
+                                                ui.label(egui::RichText::new("PRE").monospace().size(8.0).color(colors.text));
+                                                ui.label(egui::RichText::new(format!("{:.0}ms", reverb.pre_delay_ms)).monospace().size(8.0).color(colors.text_dim));
+                                            });
+                                            ui.add_space(4.0);
+                                            ui.vertical(|ui| {
+                                                knob(ui, &mut settings.return_level, 0.0..=1.0, 30.0);
+                                                ui.label(egui::RichText::new("RTN").monospace().size(8.0).color(colors.text));
+                                            });
+                                        });
+                                    },
+                                }
+                            });
+                            ui.add_space(4.0);
+                        }
+                        if let Some(bus) = removed_bus {
+                            project.aux_buses.remove(bus);
+                            // Later buses move down a slot, so their sends move with them
+                            for track in project.tracks.iter_mut() {
+                                track.sends.copy_within(bus + 1.., bus);
+                                track.sends[MAX_AUX_BUSES - 1] = 0.0;
+                            }
+                        }
+
+                        ui.add_space(8.0);
+
+                        // Master bus (project-wide): glue compressor into the limiter
+                        let master = &mut project.master;
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new("MASTER BUS").size(9.0).color(colors.text_dim));
+                            if ui.selectable_label(master.compressor, "COMP").clicked() {
+                                master.compressor = !master.compressor;
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.vertical(|ui| {
+                                knob(ui, &mut master.ceiling_db, -12.0..=0.0, 30.0);
+                                ui.label(egui::RichText::new("CEIL").monospace().size(8.0).color(colors.text));
+                                ui.label(egui::RichText::new(format!("{:.1}dB", master.ceiling_db)).monospace().size(8.0).color(colors.text_dim));
+                            });
+                            if master.compressor {
+                                ui.add_space(4.0);
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut master.threshold_db, -40.0..=0.0, 30.0);
+                                    ui.label(egui::RichText::new("THR").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.0}dB", master.threshold_db)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                                ui.add_space(4.0);
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut master.ratio, 1.0..=10.0, 30.0);
+                                    ui.label(egui::RichText::new("RATIO").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.1}:1", master.ratio)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                            }
+                        });
+                        if master.compressor {
+                            ui.horizontal(|ui| {
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut master.attack_ms, 0.1..=100.0, 30.0);
+                                    ui.label(egui::RichText::new("ATK").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.1}ms", master.attack_ms)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                                ui.add_space(4.0);
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut master.release_ms, 10.0..=1000.0, 30.0);
+                                    ui.label(egui::RichText::new("REL").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("{:.0}ms", master.release_ms)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                                ui.add_space(4.0);
+                                ui.vertical(|ui| {
+                                    knob(ui, &mut master.makeup_db, 0.0..=12.0, 30.0);
+                                    ui.label(egui::RichText::new("MAKEUP").monospace().size(8.0).color(colors.text));
+                                    ui.label(egui::RichText::new(format!("+{:.1}dB", master.makeup_db)).monospace().size(8.0).color(colors.text_dim));
+                                });
+                            });
+                        }
                     });
                 });
             });
@@ -775,16 +1622,18 @@ fn ui_system(
     egui::CentralPanel::default().show(ctx, |ui| {
         let rect = ui.available_rect_before_wrap();
         let response = ui.allocate_rect(rect, egui::Sense::click_and_drag());
+        let pattern_rows = project.current_pattern_rows();
+        let track_count = project.tracks.len();
         
         // Handle mouse clicks
         if response.clicked() {
             if let Some(pos) = response.interact_pointer_pos() {
-                let col_width = rect.width() / (NUM_CHANNELS + 1) as f32; // +1 for row number column
+                let col_width = rect.width() / (track_count + 1) as f32; // +1 for row number column
                 let row_height = 22.0;
                 let col = ((pos.x - rect.left()) / col_width) as usize;
                 let row = ((pos.y - rect.top() + *scroll_y) / row_height) as usize;
                 // Column 0 is row numbers, channels start from column 1
-                if col > 0 && col <= NUM_CHANNELS && row < ROWS_PER_PATTERN {
+                if col > 0 && col <= track_count && row < pattern_rows {
                     let ch = col - 1; // Convert column to channel (0-indexed)
                     ui_state.selected_note = Some((row, ch));
                     project.current_channel = ch;
@@ -796,17 +1645,15 @@ fn ui_system(
         // Scroll handling
         if ui.is_rect_visible(rect) {
             let scroll_delta = ui.input(|i| i.raw_scroll_delta.y);
-            if scroll_delta != 0.0 {
-                let total_rows = ROWS_PER_PATTERN as f32;
-                let row_h = 22.0;
-                let total_h = total_rows * row_h;
-                let view_h = rect.height();
-                
-                if total_h > view_h {
-                    *scroll_y -= scroll_delta;
-                    *scroll_y = scroll_y.clamp(0.0, total_h - view_h);
-                }
+            let total_rows = pattern_rows as f32;
+            let row_h = 22.0;
+            let total_h = total_rows * row_h;
+            let view_h = rect.height();
+            if scroll_delta != 0.0 && total_h > view_h {
+                *scroll_y -= scroll_delta;
             }
+            // Also pulls the view back when a shorter pattern is opened
+            *scroll_y = scroll_y.clamp(0.0, (total_h - view_h).max(0.0));
             
             if project.playing {
                 let current_row_y = project.current_row as f32 * 22.0;
@@ -816,7 +1663,7 @@ fn ui_system(
         }
 
         let painter = ui.painter_at(rect);
-        let col_width = rect.width() / (NUM_CHANNELS + 1) as f32; // +1 for row number column
+        let col_width = rect.width() / (track_count + 1) as f32; // +1 for row number column
         let row_height = 22.0;
         let dt = ui.input(|i| i.stable_dt);
 
@@ -825,7 +1672,7 @@ fn ui_system(
             let mut rng = rand::thread_rng();
             
             if rng.gen::<f32>() < 0.02 * dt {
-                let col = rng.gen_range(1..=NUM_CHANNELS); // Skip column 0 (row numbers)
+                let col = rng.gen_range(1..=track_count); // Skip column 0 (row numbers)
                 let x = rect.left() + col as f32 * col_width + col_width / 2.0;
                 let speed = rng.gen_range(150.0..400.0);
                 let char_interval = rng.gen_range(0.05..0.15);
@@ -893,7 +1740,7 @@ fn ui_system(
         }
 
         // GRID LINES - Add one extra column for row numbers, then channels
-        for i in 0..=NUM_CHANNELS + 1 {
+        for i in 0..=track_count + 1 {
             let x = rect.left() + i as f32 * col_width;
             painter.line_segment(
                 [egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())],
@@ -904,7 +1751,7 @@ fn ui_system(
         let start_row_idx = (*scroll_y / row_height).floor() as isize;
         let end_row_idx = ((*scroll_y + rect.height()) / row_height).ceil() as isize;
         for r in start_row_idx..=end_row_idx {
-            if r >= 0 && r < ROWS_PER_PATTERN as isize {
+            if r >= 0 && r < pattern_rows as isize {
                 let row_y = rect.top() + (r as f32 * row_height) - *scroll_y;
                 painter.line_segment(
                     [egui::pos2(rect.left(), row_y), egui::pos2(rect.right(), row_y)],
@@ -915,9 +1762,9 @@ fn ui_system(
         
         // RENDER NOTES WITH DETAILED INFO
         let current_pattern_idx = project.current_pattern;
-        if let Some(pattern) = project.patterns.get(current_pattern_idx) {
+        if let Some(pattern) = project.patterns().get(current_pattern_idx) {
             for r in start_row_idx..=end_row_idx {
-                if r >= 0 && r < ROWS_PER_PATTERN as isize {
+                if r >= 0 && r < pattern.rows.len() as isize {
                     let row_y = rect.top() + (r as f32 * row_height) - *scroll_y;
                     let row_data = &pattern.rows[r as usize];
                     
@@ -960,19 +1807,26 @@ fn ui_system(
                                 0.0,
                                 egui::Stroke::new(3.0, colors.primary)
                             );
-                            // Cursor indicator
+                            // Cursor indicator: left for the note, centre for the instrument, right for the effect column
+                            let cursor_x = match *edit_field {
+                                EditField::Note => cell_rect.left() + 3.0,
+                                EditField::Instrument(_) => cell_rect.center().x,
+                                EditField::Effect(_) => cell_rect.right() - 3.0,
+                            };
                             painter.circle_filled(
-                                egui::pos2(cell_rect.left() + 3.0, cell_rect.center().y),
+                                egui::pos2(cursor_x, cell_rect.center().y),
                                 2.0,
                                 colors.primary
                             );
                         }
+                        let effect_label = effect_text(cell);
                         
                         if let Some(note) = cell.note {
-                            let inst = (cell.instrument.unwrap_or(0) % 4) as usize;
-                            let note_color = colors.note_colors[inst];
+                            let instrument = cell.instrument.and_then(|i| project.instruments.get(i as usize));
+                            let note_color = instrument_color(&colors, instrument);
                             let velocity = cell.volume.unwrap_or(64);
-                            let cc_value = 64; // Default CC value
+                            let cc_value = cell_filter_cutoff(cell);
+                            let pan_value = cell_pan(cell);
                             
                             painter.rect_filled(
                                 cell_rect,
@@ -989,16 +1843,31 @@ fn ui_system(
                             // Only show note name by default, add V/CC only if changed
                             let note_name_str = note_name(note);
                             let mut note_text = note_name_str.clone();
+
+                            // The instrument column, as the bank index
+                            if let Some(inst) = cell.instrument {
+                                note_text.push_str(&format!(" {:02X}", inst));
+                            }
                             
                             // Add velocity if not default (64)
                             if velocity != 64 {
                                 note_text.push_str(&format!(" V:{}", velocity));
                             }
                             
-                            // Add CC if not default (64)
-                            if cc_value != 64 {
+                            // Add CC if the note carries a cutoff command
+                            if let Some(cc_value) = cc_value {
                                 note_text.push_str(&format!(" CC:{}", cc_value));
                             }
+
+                            // And its pan position if it carries a pan command
+                            if let Some(pan_value) = pan_value {
+                                note_text.push_str(&format!(" P:{}", pan_label(effect_to_pan(pan_value))));
+                            }
+
+                            // Any other effect as typed
+                            if let Some(effect_label) = effect_label.filter(|_| cc_value.is_none() && pan_value.is_none()) {
+                                note_text.push_str(&format!(" {}", effect_label));
+                            }
                             
                             // Single line display
                             painter.text(
@@ -1008,6 +1877,22 @@ fn ui_system(
                                 egui::FontId::monospace(10.0),
                                 note_color
                             );
+                        } else if let Some(inst) = cell.instrument {
+                            // An instrument with no note, so it can be seen and cleared
+                            let text = match effect_label {
+                                Some(effect_label) => format!("{:02X} {}", inst, effect_label),
+                                None => format!("{:02X}", inst),
+                            };
+                            painter.text(cell_rect.center(), egui::Align2::CENTER_CENTER, text, egui::FontId::monospace(10.0), colors.text_dim);
+                        } else if let Some(effect_label) = effect_label {
+                            // Effect on its own, e.g. a slide continuing under a held note
+                            painter.text(
+                                cell_rect.center(),
+                                egui::Align2::CENTER_CENTER,
+                                effect_label,
+                                egui::FontId::monospace(10.0),
+                                colors.text_dim
+                            );
                         }
                     }
                 }
@@ -1035,14 +1920,14 @@ fn ui_system(
         // Check if hovering over pattern editor cells
         if let Some(pos) = pointer_pos {
             if pos.x >= rect.left() && pos.x <= rect.right() && pos.y >= rect.top() && pos.y <= rect.bottom() {
-                let col_width = rect.width() / (NUM_CHANNELS + 1) as f32;
+                let col_width = rect.width() / (track_count + 1) as f32;
                 let row_height = 22.0;
                 let col = ((pos.x - rect.left()) / col_width) as usize;
                 let row = ((pos.y - rect.top() + *scroll_y) / row_height) as usize;
                 
-                if col > 0 && col <= NUM_CHANNELS && row < ROWS_PER_PATTERN {
+                if col > 0 && col <= track_count && row < project.current_pattern_rows() {
                     let ch = col - 1;
-                    if let Some(pattern) = project.patterns.get(project.current_pattern) {
+                    if let Some(pattern) = project.patterns().get(project.current_pattern) {
                         if let Some(row_data) = pattern.rows.get(row) {
                             if let Some(cell) = row_data.channels.get(ch) {
                                 if let Some(note) = cell.note {
@@ -1052,7 +1937,12 @@ fn ui_system(
                                     if velocity != 64 {
                                         hint.push_str(&format!(" V:{}", velocity));
                                     }
+                                    if let (Some(effect), Some(text)) = (cell.effect, effect_text(cell)) {
+                                        hint.push_str(&format!(" {} ({})", text, effect.describe(cell.effect_value.unwrap_or(0))));
+                                    }
                                     ui_state.hover_tooltip = Some(hint);
+                                } else if let (Some(effect), Some(text)) = (cell.effect, effect_text(cell)) {
+                                    ui_state.hover_tooltip = Some(format!("Row {} Ch{}: {} ({})", row, ch + 1, text, effect.describe(cell.effect_value.unwrap_or(0))));
                                 } else {
                                     ui_state.hover_tooltip = Some(format!("Row {} Ch{}: Empty", row, ch + 1));
                                 }
@@ -1083,7 +1973,74 @@ fn ui_system(
     });
 }
 
+/// Notes are coloured by their instrument's waveform; other sources, and instruments
+/// missing from the bank, use the theme's primary colour.
+fn instrument_color(colors: &ThemeColors, instrument: Option<&Instrument>) -> egui::Color32 {
+    match instrument.map(|inst| &inst.settings) {
+        Some(settings) if settings.source == SourceType::Oscillator => {
+            let index = Waveform::ALL.iter().position(|&w| w == settings.oscillator.waveform).unwrap_or(0);
+            colors.note_colors[index]
+        },
+        _ => colors.primary,
+    }
+}
+
+/// The cutoff a Zxx command on this cell sets, as a 0-127 CC value.
+fn cell_filter_cutoff(cell: &ChannelData) -> Option<u8> {
+    match (cell.effect, cell.effect_value) {
+        (Some(EffectType::FilterControl), Some(value)) if value < FILTER_RESONANCE_BASE => Some(value),
+        _ => None,
+    }
+}
+
+/// The 8xx value of a pan command on this cell.
+fn cell_pan(cell: &ChannelData) -> Option<u8> {
+    match (cell.effect, cell.effect_value) {
+        (Some(EffectType::Panning), Some(value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// "C" for centre, otherwise the side and how far, e.g. "L40".
+fn pan_label(pan: f32) -> String {
+    let percent = (pan * 100.0).round() as i32;
+    match percent {
+        0 => "C".to_string(),
+        p if p < 0 => format!("L{}", -p),
+        p => format!("R{}", p),
+    }
+}
+
+/// Aux buses are lettered A, B, C... in the order they were added.
+fn bus_letter(bus: usize) -> char {
+    (b'A' + bus as u8) as char
+}
+
+fn next_aux_effect(effect: AuxEffect) -> AuxEffect {
+    let idx = AuxEffect::ALL.iter().position(|e| *e == effect).unwrap_or(0);
+    AuxEffect::ALL[(idx + 1) % AuxEffect::ALL.len()]
+}
+
+fn instrument_names(project: &ProjectData) -> Vec<String> {
+    project.instruments.iter().map(|inst| inst.name.clone()).collect()
+}
+
+fn instrument_label(names: &[String], index: u8) -> String {
+    match names.get(index as usize) {
+        Some(name) => format!("{:02X} {}", index, name),
+        None => format!("{:02X} ---", index),
+    }
+}
+
+/// Cycles through the bank, wrapping back to the first instrument.
+fn next_instrument(names: &[String], index: u8) -> u8 {
+    if names.is_empty() { 0 } else { ((index as usize + 1) % names.len()) as u8 }
+}
+
 fn note_name(midi_note: u8) -> String {
+    if midi_note == NOTE_OFF {
+        return "OFF".to_string();
+    }
     let notes = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
     let octave = (midi_note / 12) as i32 - 1;
     let note_idx = (midi_note % 12) as usize;
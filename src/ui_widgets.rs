@@ -139,3 +139,74 @@ pub fn cyber_slider(ui: &mut Ui, value: &mut f32, range: std::ops::RangeInclusiv
     
     response
 }
+
+/// Single-cycle waveform editor: drag across it to draw the table.
+/// Values run from -1 (bottom) to 1 (top); strokes are interpolated between pointer samples.
+pub fn wave_editor(ui: &mut Ui, points: &mut [f32], size: Vec2) -> Response {
+    let (rect, mut response) = ui.allocate_exact_size(size, Sense::drag());
+    let last_id = response.id.with("last_point");
+
+    if response.dragged() && !points.is_empty() {
+        if let Some(pos) = response.interact_pointer_pos() {
+            let len = points.len();
+            let to_point = |pos: egui::Pos2| {
+                let x = ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0) * (len - 1) as f32;
+                let y = (1.0 - 2.0 * (pos.y - rect.top()) / rect.height()).clamp(-1.0, 1.0);
+                (x.round() as usize, y)
+            };
+            let (x, y) = to_point(pos);
+            let (last_x, last_y) = ui.data(|d| d.get_temp::<egui::Pos2>(last_id)).map_or((x, y), to_point);
+
+            // Fill every point between the previous pointer sample and this one
+            let (from, to) = if last_x <= x { ((last_x, last_y), (x, y)) } else { ((x, y), (last_x, last_y)) };
+            for (i, point) in points.iter_mut().enumerate().take(to.0 + 1).skip(from.0) {
+                let t = if to.0 == from.0 { 1.0 } else { (i - from.0) as f32 / (to.0 - from.0) as f32 };
+                *point = from.1 + (to.1 - from.1) * t;
+            }
+            ui.data_mut(|d| d.insert_temp(last_id, pos));
+            response.mark_changed();
+        }
+    } else {
+        ui.data_mut(|d| d.remove::<egui::Pos2>(last_id));
+    }
+
+    if ui.is_rect_visible(rect) {
+        ui.painter().rect_filled(rect, 2.0, Color32::from_rgb(10, 10, 10));
+        ui.painter().rect_stroke(rect, 2.0, Stroke::new(1.0, Color32::from_rgb(0, 100, 0)));
+
+        // Zero line
+        ui.painter().line_segment(
+            [egui::pos2(rect.left(), rect.center().y), egui::pos2(rect.right(), rect.center().y)],
+            Stroke::new(1.0, Color32::from_rgb(0, 60, 0)),
+        );
+
+        if points.len() > 1 {
+            let step = rect.width() / (points.len() - 1) as f32;
+            let line: Vec<egui::Pos2> = points.iter().enumerate()
+                .map(|(i, &v)| egui::pos2(rect.left() + i as f32 * step, rect.center().y - v.clamp(-1.0, 1.0) * rect.height() / 2.0))
+                .collect();
+            ui.painter().add(egui::Shape::line(line, Stroke::new(1.5, Color32::from_rgb(0, 255, 0))));
+        }
+    }
+
+    response
+}
+
+/// Horizontal gain-reduction meter: fills from the right as the reduction deepens, up to `range_db`.
+pub fn reduction_meter(ui: &mut Ui, reduction_db: f32, range_db: f32, size: Vec2) -> Response {
+    let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+
+    if ui.is_rect_visible(rect) {
+        ui.painter().rect_filled(rect, 1.0, Color32::from_rgb(10, 10, 10));
+        let t = (reduction_db / range_db).clamp(0.0, 1.0);
+        if t > 0.0 {
+            let fill = egui::Rect::from_min_max(egui::pos2(rect.right() - rect.width() * t, rect.top()), rect.max);
+            // Green for gentle gain riding, amber once it's working hard
+            let color = if t < 0.5 { Color32::from_rgb(0, 200, 0) } else { Color32::from_rgb(255, 170, 0) };
+            ui.painter().rect_filled(fill, 1.0, color);
+        }
+        ui.painter().rect_stroke(rect, 1.0, Stroke::new(1.0, Color32::from_rgb(0, 100, 0)));
+    }
+
+    response
+}